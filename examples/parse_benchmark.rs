@@ -0,0 +1,112 @@
+//! Time the parallel OBJ parser against the sequential `obj::parse` on a bundled asset scaled up
+//! by repeating it, with one thread and then doubling up to the given number of threads.
+//!
+//!     cargo run --release --example parse_benchmark -- [copies] [threads]
+
+extern crate obj_rs;
+
+use obj_rs::wavefront::obj::{ self, ParseOptions };
+use std::env;
+use std::fs;
+use std::thread;
+use std::time::{ Duration, Instant };
+
+const ASSET: &str = "assets/cone_smoothtorus_cube.obj";
+
+/// Times each parser is run, keeping the fastest
+const RUNS: usize = 3;
+
+/// Repeat the statements of an OBJ file, offsetting face indices so each copy refers to its own
+/// vertices and renaming objects so copies stay apart
+fn scale(source: &str, copies: usize) -> String {
+	let count = |keyword: &str| source.lines().filter(|line| line.split_whitespace().next() == Some(keyword)).count();
+	let counts = [ count("v"), count("vt"), count("vn") ];
+
+	let mut scaled = String::with_capacity(source.len() * copies);
+
+	for copy in 0..copies {
+		for line in source.lines() {
+			let mut tokens = line.split_whitespace();
+
+			match tokens.next() {
+				Some("f") => {
+					scaled.push('f');
+
+					for vertex in tokens {
+						let indices: Vec<String> = vertex.split('/')
+							.zip(counts.iter())
+							.map(|(index, &count)| match index.parse::<usize>() {
+								Ok(index) => (index + copy * count).to_string(),
+								Err(_) => String::from(index),
+							})
+							.collect();
+
+						scaled.push(' ');
+						scaled.push_str(&indices.join("/"));
+					}
+				},
+				Some("o") => scaled.push_str(&format!("{}_{}", line, copy)),
+				_ => scaled.push_str(line),
+			}
+
+			scaled.push('\n');
+		}
+	}
+
+	scaled
+}
+
+/// Fastest of several runs of a parser, and its result
+fn time<T, F: Fn() -> T>(parse: F) -> (Duration, T) {
+	let mut best = None;
+
+	for _ in 0..RUNS {
+		let start = Instant::now();
+		let result = parse();
+		let elapsed = start.elapsed();
+
+		let faster = match best {
+			Some((fastest, _)) => elapsed < fastest,
+			None => true,
+		};
+
+		if faster {
+			best = Some((elapsed, result));
+		}
+	}
+
+	best.unwrap()
+}
+
+fn main() {
+	let mut args = env::args().skip(1).map(|arg| arg.parse::<usize>().expect("arguments must be numbers"));
+	let copies = args.next().unwrap_or(100);
+	let max_threads = args.next().unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+
+	let source = fs::read_to_string(ASSET).expect("couldn't read the asset, run from the repository root");
+	let input = scale(&source, copies);
+	let options = ParseOptions::default();
+
+	println!("{} copies of {}: {:.1} MiB", copies, ASSET, input.len() as f64 / (1024.0 * 1024.0));
+
+	let (baseline, model) = time(|| obj::parse(input.as_bytes()).unwrap());
+	let faces: usize = model.0.objects().iter().map(|object| object.faces().len()).sum();
+
+	println!("{} vertices, {} faces", model.0.vertices().len(), faces);
+	println!("parse:                      {:>8.1} ms", baseline.as_secs_f64() * 1000.0);
+
+	let mut threads = 1;
+
+	loop {
+		let (elapsed, _) = time(|| obj::parse_parallel(input.as_bytes(), &options, threads).unwrap());
+
+		println!("parse_parallel, {:>2} threads: {:>8.1} ms, {:.2}x", threads, elapsed.as_secs_f64() * 1000.0,
+			baseline.as_secs_f64() / elapsed.as_secs_f64());
+
+		if threads >= max_threads {
+			break;
+		}
+
+		threads = (threads * 2).min(max_threads);
+	}
+}
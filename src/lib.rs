@@ -0,0 +1,13 @@
+//! Parser for Wavefront OBJ and MTL files.
+//!
+//! Needs Rust 1.70 or later. The model viewer behind the `viewer` feature uses glium 0.36, whose
+//! dependencies may need a newer Rust.
+
+#[macro_use]
+extern crate nom;
+
+#[cfg(feature = "glium")]
+#[macro_use]
+extern crate glium;
+
+pub mod wavefront;
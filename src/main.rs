@@ -1,30 +1,26 @@
-#[macro_use]
-extern crate nom;
-
 #[macro_use]
 extern crate glium;
 
+extern crate obj_rs;
 extern crate nalgebra;
-extern crate piston;
-extern crate piston_window;
-extern crate glutin_window;
 
-mod wavefront;
-mod glium_window;
+use std::f32::consts::FRAC_PI_2;
+use std::time::Instant;
 
-use wavefront::{ obj, mtl };
-use glium_window::GliumWindow;
+use obj_rs::wavefront::{ obj, mtl };
 
 use glium::{ Surface };
 use glium::index::{ PrimitiveType, NoIndices };
 use glium::draw_parameters::BackfaceCullingMode;
+use glium::backend::glutin::SimpleWindowBuilder;
+use glium::backend::glutin::glutin::config::ConfigTemplateBuilder;
+use glium::winit::event::{ Event, WindowEvent, KeyEvent, ElementState };
+use glium::winit::event_loop::EventLoop;
+use glium::winit::keyboard::{ Key, NamedKey };
 use nalgebra::{ Point3, Vector3, Perspective3, Isometry3 };
-use piston_window::{ Input, OpenGL };
-use piston::event_loop::{ Events, EventSettings, EventLoop };
-use piston::window::{ WindowSettings };
 
 fn get_matrices(eye: &Point3<f32>, target: &Point3<f32>, projection: &Perspective3<f32>) -> ([[f32; 4]; 4], [[f32; 4]; 4]) {
-    let view = Isometry3::look_at_rh(&eye, &target, &Vector3::z());
+    let view = Isometry3::look_at_rh(eye, target, &Vector3::z());
 
     let p = projection.as_matrix().as_slice();
     let v = view.to_homogeneous();
@@ -60,28 +56,39 @@ fn model_matrix(translate: &Vector3<f32>, rotate: &Vector3<f32>) -> [[f32; 4]; 4
 }
 
 fn main() {
-    let model = obj::load("./assets/twocubes.obj");
+    let model = obj::load("./assets/twocubes.obj").unwrap();
 
-    let objects = model.unwrap().to_vertices();
+    let objects = model.to_vertices();
+    let lines = model.to_line_vertices();
+    let points = model.to_point_vertices();
 
     // building the display, ie. the main object
-    let mut display: GliumWindow = WindowSettings::new("Test", [1280, 720])
-        .exit_on_esc(true)
-        .samples(4)
-        .opengl(OpenGL::V3_2)
-        .vsync(true)
-        .build()
-        .unwrap();
-
-    let buffers: Vec<(glium::VertexBuffer<obj::BufferVertex>, mtl::WavefrontMaterial)> = objects.iter().map(|&(ref vertices, ref material)| {
-        (
-            glium::VertexBuffer::new(&display, &vertices.as_slice()).unwrap(),
-            (*material).clone()
-        )
-    }).collect();
+    let event_loop = EventLoop::builder().build().unwrap();
+
+    let (window, display) = SimpleWindowBuilder::new()
+        .with_title("Test")
+        .with_inner_size(1280, 720)
+        .with_config_template_builder(ConfigTemplateBuilder::new().with_depth_size(24).with_multisampling(4))
+        .build(&event_loop);
+
+    let upload = |batches: &Vec<(Vec<obj::BufferVertex>, mtl::WavefrontMaterial)>| -> Vec<(glium::VertexBuffer<obj::BufferVertex>, mtl::WavefrontMaterial)> {
+        batches.iter().map(|(vertices, material)| {
+            (
+                glium::VertexBuffer::new(&display, vertices.as_slice()).unwrap(),
+                material.clone()
+            )
+        }).collect()
+    };
+
+    // Lines and points have no normals so are drawn unlit in their diffuse colour
+    let buffers: Vec<(PrimitiveType, bool, glium::VertexBuffer<obj::BufferVertex>, mtl::WavefrontMaterial)> = upload(&objects).into_iter()
+        .map(|(buffer, material)| (PrimitiveType::TrianglesList, false, buffer, material))
+        .chain(upload(&lines).into_iter().map(|(buffer, material)| (PrimitiveType::LinesList, true, buffer, material)))
+        .chain(upload(&points).into_iter().map(|(buffer, material)| (PrimitiveType::Points, true, buffer, material)))
+        .collect();
 
     // A perspective projection.
-    let perspective = Perspective3::new(16.0f32 / 9.0, 3.14 / 2.0, 0.1, 1000.0);
+    let perspective = Perspective3::new(16.0f32 / 9.0, FRAC_PI_2, 0.1, 1000.0);
     let target = Point3::new(0.0, 0.0, 0.0);
 
     let program = program!(&display,
@@ -116,6 +123,12 @@ fn main() {
 
                 uniform vec3 mat_ambient;
                 uniform vec3 mat_diffuse;
+                uniform vec3 mat_specular;
+                uniform float mat_shininess;
+                uniform vec3 view_pos;
+                uniform bool unlit;
+                uniform bool with_ambient;
+                uniform bool with_specular;
 
                 out vec4 f_color;
 
@@ -123,16 +136,29 @@ fn main() {
                 const vec3 LIGHT_COLOR = vec3(1.0, 1.0, 1.0);
 
                 void main() {
+                    if (unlit) {
+                        f_color = vec4(mat_diffuse, 1.0f);
+                        return;
+                    }
+
                     float ambientStrength = 0.1f;
-                    vec3 ambient = ambientStrength * LIGHT_COLOR;
+                    vec3 ambient = with_ambient ? ambientStrength * LIGHT_COLOR * mat_ambient : vec3(0.0f);
 
                     vec3 norm = normalize(v_normal);
                     vec3 lightDir = normalize(LIGHT_POS - frag_pos);
 
                     float diff = max(dot(norm, lightDir), 0.0);
-                    vec3 diffuse = diff * LIGHT_COLOR;
+                    vec3 diffuse = diff * LIGHT_COLOR * mat_diffuse;
 
-                    vec3 result = (ambient + diffuse) * mat_diffuse;
+                    // Blinn-Phong highlight
+                    vec3 specular = vec3(0.0f);
+
+                    if (with_specular && diff > 0.0) {
+                        vec3 halfway = normalize(lightDir + normalize(view_pos - frag_pos));
+                        specular = pow(max(dot(norm, halfway), 0.0), mat_shininess) * LIGHT_COLOR * mat_specular;
+                    }
+
+                    vec3 result = ambient + diffuse + specular;
                     f_color = vec4(result, 1.0f);
                 }
             ",
@@ -146,43 +172,69 @@ fn main() {
             .. Default::default()
         },
         backface_culling: BackfaceCullingMode::CullingDisabled,
+        point_size: Some(4.0),
         .. Default::default()
     };
 
-    let mut angle = 0.0;
-
-    let mut events = Events::new(EventSettings::new()).ups(100);
-
-    while let Some(e) = events.next(&mut display) {
-        match e {
-            Input::Render(_) => {
-                let eye = Point3::new(3.0, 3.0, 3.0);
-
-                let (perspective_mat, view_mat) = get_matrices(&eye, &target, &perspective);
-
-                let mut target = display.draw();
-                target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
-
-                for &(ref buffer, ref material) in buffers.iter() {
-                    // building the uniforms
-                    let uniforms = uniform! {
-                        persp_matrix: perspective_mat,
-                        view_matrix: view_mat,
-                        model_matrix: model_matrix(&Vector3::new(0.0, 0.0, 0.0), &Vector3::new(0.0, 0.0, angle)),
-
-                        mat_ambient: material.ambient,
-                        mat_diffuse: material.diffuse,
-                    };
-
-                    target.draw(buffer, NoIndices(PrimitiveType::TrianglesList), &program, &uniforms, &params).unwrap();
-                }
-
-                target.finish().unwrap();
-            },
-            Input::Update(u) => {
-                angle += 2.0 * u.dt as f32;
+    let start = Instant::now();
+
+    // The closure form of `run` is deprecated in favour of `ApplicationHandler`, but is all a
+    // window drawing continuously needs
+    #[allow(deprecated)]
+    event_loop.run(move |event, window_target| {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => window_target.exit(),
+                WindowEvent::KeyboardInput { event: KeyEvent { logical_key: Key::Named(NamedKey::Escape), state: ElementState::Pressed, .. }, .. } => {
+                    window_target.exit()
+                },
+                WindowEvent::Resized(size) => display.resize(size.into()),
+                WindowEvent::RedrawRequested => {
+                    let angle = 2.0 * start.elapsed().as_secs_f32();
+                    let eye = Point3::new(3.0, 3.0, 3.0);
+
+                    let (perspective_mat, view_mat) = get_matrices(&eye, &target, &perspective);
+
+                    let mut frame = display.draw();
+                    frame.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+
+                    for &(primitive, unlit, ref buffer, ref material) in buffers.iter() {
+                        // Models 0 to 2 are drawn as specified, the ray traced and transparent ones
+                        // above them as their highlight model
+                        let model = material.illumination_model;
+
+                        // A shadow matte only shows the shadows cast onto it, and nothing casts any
+                        // here, so it would be invisible
+                        if model == mtl::IlluminationModel::ShadowMatte {
+                            continue;
+                        }
+
+                        // building the uniforms
+                        let uniforms = uniform! {
+                            persp_matrix: perspective_mat,
+                            view_matrix: view_mat,
+                            model_matrix: model_matrix(&Vector3::new(0.0, 0.0, 0.0), &Vector3::new(0.0, 0.0, angle)),
+
+                            mat_ambient: material.ambient,
+                            mat_diffuse: material.diffuse,
+                            mat_specular: material.specular,
+                            mat_shininess: material.specular_exponent.max(1.0),
+                            view_pos: [eye.x, eye.y, eye.z],
+                            unlit: unlit || !model.lit(),
+                            with_ambient: model.ambient(),
+                            with_specular: model.specular(),
+                        };
+
+                        frame.draw(buffer, NoIndices(primitive), &program, &uniforms, &params).unwrap();
+                    }
+
+                    frame.finish().unwrap();
+                },
+                _ => {}
             },
+            // Redraw continuously so the model keeps turning
+            Event::AboutToWait => window.request_redraw(),
             _ => {}
         }
-    }
-}
\ No newline at end of file
+    }).unwrap();
+}
@@ -0,0 +1,330 @@
+use nom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+/// Where in a source file an error occurred
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+	/// File the error occurred in, if the input was loaded from disk
+	pub path: Option<PathBuf>,
+	/// One-based line number
+	pub line: usize,
+	/// One-based column number, counted in bytes
+	pub column: usize,
+	/// Text of the offending line, without its line ending
+	pub text: String,
+}
+
+impl Location {
+	pub fn new(line: usize, column: usize, text: &[u8]) -> Location {
+		let text = match text.iter().rposition(|&c| c != b'\n' && c != b'\r') {
+			Some(end) => &text[..end + 1],
+			None => &text[..0],
+		};
+
+		Location {
+			path: None,
+			line,
+			column,
+			text: String::from_utf8_lossy(text).into_owned(),
+		}
+	}
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.path {
+			Some(ref path) => write!(f, "{}:{}:{}", path.display(), self.line, self.column),
+			None => write!(f, "line {}, column {}", self.line, self.column),
+		}
+	}
+}
+
+/// Errors produced while loading or parsing OBJ and MTL files
+#[derive(Debug)]
+pub enum WavefrontError {
+	/// A file could not be opened or read
+	Io { path: PathBuf, error: io::Error },
+	/// A stream being parsed could not be read
+	Read { error: io::Error },
+	/// A statement could not be parsed
+	Syntax { location: Location, message: String },
+	/// An element references a vertex, texture vertex or normal that does not exist
+	BadIndex { location: Location, message: String },
+	/// An element is malformed, e.g. a face with too few vertices or mixed index forms
+	InvalidElement { location: Location, message: String },
+	/// A statement appeared somewhere it is not allowed, e.g. a material property before `newmtl`
+	UnexpectedStatement { location: Location, message: String },
+	/// A statement whose keyword isn't part of the format. Only an error in strict mode.
+	UnknownStatement { location: Location, message: String },
+	/// The material library named by an `mtllib` statement could not be opened
+	MissingMaterialLibrary { path: PathBuf, location: Location, error: io::Error },
+}
+
+impl WavefrontError {
+	/// The location of the error in its source file, if it has one
+	pub fn location(&self) -> Option<&Location> {
+		match *self {
+			WavefrontError::Io { .. } | WavefrontError::Read { .. } => None,
+			WavefrontError::Syntax { ref location, .. } |
+			WavefrontError::BadIndex { ref location, .. } |
+			WavefrontError::InvalidElement { ref location, .. } |
+			WavefrontError::UnexpectedStatement { ref location, .. } |
+			WavefrontError::UnknownStatement { ref location, .. } |
+			WavefrontError::MissingMaterialLibrary { ref location, .. } => Some(location),
+		}
+	}
+
+	pub fn location_mut(&mut self) -> Option<&mut Location> {
+		match *self {
+			WavefrontError::Io { .. } | WavefrontError::Read { .. } => None,
+			WavefrontError::Syntax { ref mut location, .. } |
+			WavefrontError::BadIndex { ref mut location, .. } |
+			WavefrontError::InvalidElement { ref mut location, .. } |
+			WavefrontError::UnexpectedStatement { ref mut location, .. } |
+			WavefrontError::UnknownStatement { ref mut location, .. } |
+			WavefrontError::MissingMaterialLibrary { ref mut location, .. } => Some(location),
+		}
+	}
+
+	/// Attach the path of the file being parsed to the error location. A failure to read the file
+	/// becomes an `Io` error for the path.
+	pub fn with_path(mut self, path: &Path) -> WavefrontError {
+		if let WavefrontError::Read { error } = self {
+			return WavefrontError::Io { path: path.to_path_buf(), error };
+		}
+
+		if let Some(location) = self.location_mut() {
+			location.path = Some(path.to_path_buf());
+		}
+
+		self
+	}
+
+	/// Turn an error in a single statement into a warning that the statement was skipped. Errors
+	/// that aren't about a statement are returned unchanged.
+	pub fn into_warning(self) -> Result<Warning, WavefrontError> {
+		match self {
+			WavefrontError::Syntax { location, message } |
+			WavefrontError::BadIndex { location, message } |
+			WavefrontError::InvalidElement { location, message } |
+			WavefrontError::UnexpectedStatement { location, message } |
+			WavefrontError::UnknownStatement { location, message } => Ok(Warning { location, message }),
+			WavefrontError::MissingMaterialLibrary { path, location, error } => Ok(Warning {
+				location,
+				message: format!("couldn't open material library {}: {}", path.display(), error),
+			}),
+			error => Err(error),
+		}
+	}
+}
+
+impl fmt::Display for WavefrontError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			WavefrontError::Io { ref path, ref error } => write!(f, "couldn't read {}: {}", path.display(), error),
+			WavefrontError::Read { ref error } => write!(f, "couldn't read input: {}", error),
+			WavefrontError::Syntax { ref location, ref message } |
+			WavefrontError::BadIndex { ref location, ref message } |
+			WavefrontError::InvalidElement { ref location, ref message } |
+			WavefrontError::UnexpectedStatement { ref location, ref message } |
+			WavefrontError::UnknownStatement { ref location, ref message } => {
+				write!(f, "{}: {}\n    {}", location, message, location.text)
+			},
+			WavefrontError::MissingMaterialLibrary { ref path, ref location, ref error } => {
+				write!(f, "{}: couldn't open material library {}: {}\n    {}", location, path.display(), error, location.text)
+			},
+		}
+	}
+}
+
+impl Error for WavefrontError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match *self {
+			WavefrontError::Io { ref error, .. } |
+			WavefrontError::Read { ref error } |
+			WavefrontError::MissingMaterialLibrary { ref error, .. } => Some(error),
+			_ => None
+		}
+	}
+}
+
+/// How to handle statements that are malformed or not part of the format
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParseMode {
+	/// Stop at the first malformed or unknown statement
+	#[default]
+	Strict,
+	/// Skip malformed and unknown statements, recording a warning for each. A skipped vertex
+	/// statement is replaced by a vertex at the origin so later indices still refer to the right
+	/// vertices.
+	Lenient,
+}
+
+/// A statement that was ignored or skipped while parsing. Statements the parser recognises but
+/// doesn't support are reported in both modes; malformed and unknown statements only in
+/// lenient mode, where strict mode would have stopped with an error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+	/// Where the statement is. `text` holds the statement itself.
+	pub location: Location,
+	/// Why the statement was ignored
+	pub message: String,
+}
+
+impl Warning {
+	/// Attach the path of the file being parsed to the warning location
+	pub fn with_path(mut self, path: &Path) -> Warning {
+		self.location.path = Some(path.to_path_buf());
+
+		self
+	}
+}
+
+impl fmt::Display for Warning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}: {}\n    {}", self.location, self.message, self.location.text)
+	}
+}
+
+/// Build a syntax error for a line that nom failed to parse
+pub fn syntax_error(line_number: usize, line: &[u8], err: nom::Err<&[u8]>) -> WavefrontError {
+	let remaining = match err {
+		nom::Err::Position(_, position) | nom::Err::NodePosition(_, position, _) => position.len(),
+		_ => line.len(),
+	};
+
+	WavefrontError::Syntax {
+		location: Location::new(line_number, line.len() - remaining.min(line.len()) + 1, line),
+		message: String::from("could not parse statement"),
+	}
+}
+
+/// Error for a line with more after the end of its statement
+pub fn trailing_error(line_number: usize, line: &[u8], rest: &[u8]) -> WavefrontError {
+	let start = line.len() - rest.len();
+	let skipped = rest.iter().take_while(|&&c| c == b' ' || c == b'\t').count();
+
+	WavefrontError::Syntax {
+		location: Location::new(line_number, start + skipped + 1, line),
+		message: String::from("unexpected value after the end of the statement"),
+	}
+}
+
+/// Error for a line that ended before the statement was complete
+pub fn incomplete_error(line_number: usize, line: &[u8]) -> WavefrontError {
+	let text_len = Location::new(line_number, 0, line).text.len();
+
+	WavefrontError::Syntax {
+		location: Location::new(line_number, text_len + 1, line),
+		message: String::from("unexpected end of line"),
+	}
+}
+
+/// Column of the `n`th whitespace separated token on a line, used to point at the offending
+/// value once a statement has been parsed
+pub fn token_column(line: &[u8], n: usize) -> usize {
+	let mut token = 0;
+	let mut in_token = false;
+
+	for (i, &c) in line.iter().enumerate() {
+		let is_space = c == b' ' || c == b'\t' || c == b'\r' || c == b'\n';
+
+		if !is_space && !in_token {
+			if token == n {
+				return i + 1;
+			}
+
+			token += 1;
+		}
+
+		in_token = !is_space;
+	}
+
+	1
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::obj::{ self, ParseOptions };
+
+	/// The error from parsing an OBJ file with three vertices, a texture vertex and a normal
+	/// followed by `statements`, in strict mode
+	fn parse_error(statements: &str) -> WavefrontError {
+		let input = format!("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\n{}", statements);
+		let options = ParseOptions { mode: ParseMode::Strict, ..ParseOptions::default() };
+
+		obj::parse_with_options(input.as_bytes(), &options).expect_err(statements)
+	}
+
+	fn line_and_column(error: &WavefrontError) -> (usize, usize) {
+		let location = error.location().expect("error has no location");
+
+		(location.line, location.column)
+	}
+
+	#[test]
+	fn bad_index() {
+		let error = parse_error("f 1 2 4\n");
+
+		assert!(matches!(error, WavefrontError::BadIndex { .. }), "{:?}", error);
+		assert_eq!(line_and_column(&error), (6, 7));
+		assert_eq!(error.location().unwrap().text, "f 1 2 4");
+
+		assert_eq!(line_and_column(&parse_error("f 1/1 2/2 3/1\n")), (6, 7));
+		assert_eq!(line_and_column(&parse_error("f 1//1 2//1 3//2\n")), (6, 13));
+	}
+
+	#[test]
+	fn mixed_index_forms() {
+		let error = parse_error("f 1/1 2/1 3\n");
+
+		assert!(matches!(error, WavefrontError::InvalidElement { .. }), "{:?}", error);
+		assert_eq!(line_and_column(&error), (6, 11));
+
+		assert_eq!(line_and_column(&parse_error("f 1//1 2/1/1 3//1\n")), (6, 8));
+		assert_eq!(line_and_column(&parse_error("l 1/1 2\n")), (6, 7));
+	}
+
+	#[test]
+	fn zero_index() {
+		let error = parse_error("f 1 0 2\n");
+
+		assert!(matches!(error, WavefrontError::BadIndex { .. }), "{:?}", error);
+		assert_eq!(line_and_column(&error), (6, 5));
+		assert_eq!(line_and_column(&parse_error("p 0\n")), (6, 3));
+	}
+
+	#[test]
+	fn negative_index_out_of_range() {
+		let error = parse_error("f -1 -2 -4\n");
+
+		assert!(matches!(error, WavefrontError::BadIndex { .. }), "{:?}", error);
+		assert_eq!(line_and_column(&error), (6, 9));
+		// The texture vertex is out of range, and the whole vertex is pointed at
+		assert_eq!(line_and_column(&parse_error("f -1/-2 -2/-1 -3/-1\n")), (6, 3));
+	}
+
+	#[test]
+	fn continued_statement() {
+		// Points at the physical line the index is on, not the line the statement starts on
+		let error = parse_error("f 1 2 \\\n  7\n");
+
+		assert_eq!(line_and_column(&error), (7, 3));
+		assert_eq!(error.location().unwrap().text, "  7");
+	}
+
+	#[test]
+	fn lenient_warning_location() {
+		let input = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3 4\nf 1 2 3\n";
+		let options = ParseOptions { mode: ParseMode::Lenient, ..ParseOptions::default() };
+		let (model, _) = obj::parse_with_options(input.as_bytes(), &options).unwrap();
+
+		assert_eq!(model.warnings().len(), 1);
+		assert_eq!((model.warnings()[0].location.line, model.warnings()[0].location.column), (4, 9));
+		assert_eq!(model.objects()[0].faces().len(), 1);
+	}
+}
@@ -0,0 +1,1048 @@
+use std::collections::HashMap;
+
+use super::obj::{ Basis, BasisType, BufferVertex, CurveTechnique, SurfaceTechnique };
+use super::triangulate::{ triangulate_2d, merge_holes, clip_to_rect, crosses_rect, contains, normalize, sub, dot, cross };
+
+/// Most subdivisions of a single polynomial segment, to bound the work an extreme approximation
+/// setting can cause
+const MAX_SUBDIVISIONS: usize = 256;
+
+/// Deepest recursion of curvature-dependent subdivision, which allows up to 256 pieces per segment
+const MAX_DEPTH: u32 = 8;
+
+/// Samples used to estimate the length of a segment for spatial subdivision
+const LENGTH_SAMPLES: usize = 16;
+
+/// The basis functions of one parametric direction of a curve or surface
+pub struct BasisFunctions<'a> {
+	basis_type: BasisType,
+	basis: &'a Basis,
+	count: usize,
+}
+
+impl<'a> BasisFunctions<'a> {
+	/// `count` is the number of control points in this direction, which must match the basis
+	pub fn new(basis_type: BasisType, basis: &'a Basis, count: usize) -> BasisFunctions<'a> {
+		BasisFunctions { basis_type, basis, count }
+	}
+
+	/// Range of global parameter values the basis is defined over
+	pub fn domain(&self) -> (f32, f32) {
+		let parameters = &self.basis.parameters;
+
+		match self.basis_type {
+			BasisType::BSpline => (parameters[self.basis.degree as usize], parameters[self.count]),
+			_ => (parameters[0], parameters[parameters.len() - 1]),
+		}
+	}
+
+	/// Parameter values where one polynomial segment ends and the next starts, including both
+	/// ends of the domain
+	fn breakpoints(&self) -> Vec<f32> {
+		let (start, end) = self.domain();
+
+		let mut breakpoints: Vec<f32> = self.basis.parameters.iter()
+			.cloned()
+			.filter(|&t| t >= start && t <= end)
+			.collect();
+
+		breakpoints.dedup();
+		breakpoints
+	}
+
+	/// Index of the first control point that affects the parameter value `t`, and the values of
+	/// the basis functions for it and the control points after it
+	pub fn evaluate(&self, t: f32) -> (usize, Vec<f32>) {
+		let (start, end) = self.domain();
+		let t = t.max(start).min(end);
+
+		if self.basis_type == BasisType::BSpline {
+			return self.evaluate_bspline(t);
+		}
+
+		let parameters = &self.basis.parameters;
+		let degree = self.basis.degree as usize;
+
+		// Zero length segments are skipped, so a value on a repeated parameter belongs to the
+		// segment after it
+		let segment = (0..parameters.len() - 1)
+			.filter(|&i| parameters[i] < parameters[i + 1])
+			.fold(None, |found, i| if parameters[i] <= t || found.is_none() { Some(i) } else { found })
+			.unwrap_or(0);
+
+		let span = parameters[segment + 1] - parameters[segment];
+		let s = if span > 0.0 { (t - parameters[segment]) / span } else { 0.0 };
+
+		match self.basis_type {
+			BasisType::Bezier => (segment * degree, bernstein(degree, s)),
+			BasisType::Taylor => (segment * (degree + 1), (0..degree + 1).map(|k| s.powi(k as i32)).collect()),
+			BasisType::Cardinal => {
+				// Catmull-Rom segment converted to its cubic Bezier form
+				let b = bernstein(3, s);
+
+				(segment, vec![
+					-b[1] / 6.0,
+					b[0] + b[1] + b[2] / 6.0,
+					b[1] / 6.0 + b[2] + b[3],
+					-b[2] / 6.0,
+				])
+			},
+			BasisType::BasisMatrix => {
+				let matrix = self.basis.matrix.as_ref().expect("bmatrix bases always have a matrix");
+				let step = self.basis.step.unwrap_or(1) as usize;
+
+				let values = (0..degree + 1)
+					.map(|i| (0..degree + 1).rev().fold(0.0, |sum, j| sum * s + matrix[i * (degree + 1) + j]))
+					.collect();
+
+				(segment * step, values)
+			},
+			BasisType::BSpline => unreachable!(),
+		}
+	}
+
+	/// Non-zero B-spline basis functions at `t` by the Cox-de Boor recursion
+	fn evaluate_bspline(&self, t: f32) -> (usize, Vec<f32>) {
+		let knots = &self.basis.parameters;
+		let degree = self.basis.degree as usize;
+
+		// The knot span containing t, skipping empty spans so the end of the domain belongs to
+		// the last non-empty one
+		let span = (degree..self.count)
+			.filter(|&k| knots[k] < knots[k + 1])
+			.fold(None, |found, k| if knots[k] <= t || found.is_none() { Some(k) } else { found })
+			.unwrap_or(degree);
+
+		let mut values = vec![ 0.0; degree + 1 ];
+		let mut left = vec![ 0.0; degree + 1 ];
+		let mut right = vec![ 0.0; degree + 1 ];
+
+		values[0] = 1.0;
+
+		for j in 1..degree + 1 {
+			left[j] = t - knots[span + 1 - j];
+			right[j] = knots[span + j] - t;
+
+			let mut saved = 0.0;
+
+			for r in 0..j {
+				let denominator = right[r + 1] + left[j - r];
+				let temp = if denominator != 0.0 { values[r] / denominator } else { 0.0 };
+
+				values[r] = saved + right[r + 1] * temp;
+				saved = left[j - r] * temp;
+			}
+
+			values[j] = saved;
+		}
+
+		(span - degree, values)
+	}
+
+	/// Parameter values approximating the basis between `start` and `end`, running from `start`
+	/// to `end` even if `end` is smaller. `evaluate` maps a parameter value to a point in space
+	/// for the spatial and curvature-dependent techniques. Special parameter values in the range
+	/// are always included.
+	pub fn samples(&self, start: f32, end: f32, technique: &CurveTechnique, special: &[f32], evaluate: &dyn Fn(f32) -> [f32; 3]) -> Vec<f32> {
+		let (low, high) = self.domain();
+		let reversed = end < start;
+		let (a, b) = if reversed { (end, start) } else { (start, end) };
+		let (a, b) = (a.max(low).min(high), b.max(low).min(high));
+
+		let mut bounds = vec![ a ];
+
+		bounds.extend(self.breakpoints().into_iter().filter(|&t| t > a && t < b));
+		bounds.push(b);
+
+		let mut samples = vec![ a ];
+
+		for window in bounds.windows(2) {
+			let (s0, s1) = (window[0], window[1]);
+
+			if s1 <= s0 {
+				continue;
+			}
+
+			let pieces = match *technique {
+				CurveTechnique::Parametric { resolution } => subdivisions(resolution * self.basis.degree as f32),
+				CurveTechnique::Spatial { max_length } => {
+					let length: f32 = (0..LENGTH_SAMPLES)
+						.map(|i| {
+							let (t0, t1) = (lerp(s0, s1, i as f32 / LENGTH_SAMPLES as f32), lerp(s0, s1, (i + 1) as f32 / LENGTH_SAMPLES as f32));
+
+							distance(evaluate(t0), evaluate(t1))
+						})
+						.sum();
+
+					subdivisions((length / max_length).ceil())
+				},
+				CurveTechnique::Curvature { max_distance, max_angle } => {
+					subdivide(s0, s1, 0, max_distance, max_angle, evaluate, &mut samples);
+					continue;
+				},
+			};
+
+			samples.extend((1..pieces + 1).map(|i| lerp(s0, s1, i as f32 / pieces as f32)));
+		}
+
+		samples.extend(special.iter().cloned().filter(|&t| t > a && t < b));
+
+		let mut samples = merge_samples(samples, Vec::new(), b - a);
+
+		if reversed {
+			samples.reverse();
+		}
+
+		samples
+	}
+}
+
+/// Bernstein polynomials of a degree at `s`
+fn bernstein(degree: usize, s: f32) -> Vec<f32> {
+	let mut values = vec![ 0.0; degree + 1 ];
+
+	values[0] = 1.0;
+
+	// Build up the triangle of de Casteljau weights one degree at a time
+	for j in 1..degree + 1 {
+		let mut saved = 0.0;
+
+		for value in values.iter_mut().take(j) {
+			let temp = *value;
+
+			*value = saved + (1.0 - s) * temp;
+			saved = s * temp;
+		}
+
+		values[j] = saved;
+	}
+
+	values
+}
+
+/// Number of pieces for a resolution, at least one and at most `MAX_SUBDIVISIONS`
+fn subdivisions(pieces: f32) -> usize {
+	if pieces >= 1.0 {
+		(pieces.round() as usize).min(MAX_SUBDIVISIONS)
+	} else {
+		1
+	}
+}
+
+/// Recursively split `s0..s1` until it is flat enough, pushing the end of each flat piece
+fn subdivide(s0: f32, s1: f32, depth: u32, max_distance: f32, max_angle: f32, evaluate: &dyn Fn(f32) -> [f32; 3], samples: &mut Vec<f32>) {
+	let (p0, p1) = (evaluate(s0), evaluate(s1));
+
+	let deviation = [ 0.25, 0.5, 0.75 ].iter()
+		.map(|&f| segment_distance(evaluate(lerp(s0, s1, f)), p0, p1))
+		.fold(0.0, f32::max);
+
+	let h = (s1 - s0) * 1e-3;
+	let start_tangent = sub(evaluate(s0 + h), p0);
+	let end_tangent = sub(p1, evaluate(s1 - h));
+
+	if depth >= MAX_DEPTH || (deviation <= max_distance && angle(start_tangent, end_tangent) <= max_angle) {
+		samples.push(s1);
+	} else {
+		let mid = (s0 + s1) / 2.0;
+
+		subdivide(s0, mid, depth + 1, max_distance, max_angle, evaluate, samples);
+		subdivide(mid, s1, depth + 1, max_distance, max_angle, evaluate, samples);
+	}
+}
+
+/// Sort and join two sets of samples, dropping values closer together than a small fraction of
+/// the range they cover
+fn merge_samples(mut a: Vec<f32>, b: Vec<f32>, range: f32) -> Vec<f32> {
+	let epsilon = range.abs().max(1.0) * 1e-6;
+
+	a.extend(b);
+	a.sort_by(|x, y| x.partial_cmp(y).unwrap_or(::std::cmp::Ordering::Equal));
+	a.dedup_by(|x, y| (*x - *y).abs() <= epsilon);
+	a
+}
+
+/// A curve ready to be evaluated, with its control points as `[x, y, z, weight]`
+pub struct CurveEvaluator<'a> {
+	basis: BasisFunctions<'a>,
+	points: Vec<[f32; 4]>,
+	rational: bool,
+}
+
+impl<'a> CurveEvaluator<'a> {
+	pub fn new(basis_type: BasisType, basis: &'a Basis, rational: bool, points: Vec<[f32; 4]>) -> CurveEvaluator<'a> {
+		CurveEvaluator {
+			basis: BasisFunctions::new(basis_type, basis, points.len()),
+			points,
+			rational,
+		}
+	}
+
+	pub fn point(&self, t: f32) -> [f32; 3] {
+		let (first, values) = self.basis.evaluate(t);
+
+		combine(values.iter().enumerate().map(|(i, &value)| (value, self.points[first + i])), self.rational)
+	}
+
+	/// Parameter values approximating the curve between `start` and `end`. `evaluate` maps a
+	/// parameter value to the point in space the spatial techniques measure, which for a 2D curve
+	/// is the point on its surface.
+	pub fn samples(&self, start: f32, end: f32, technique: &CurveTechnique, special: &[f32], evaluate: &dyn Fn(f32) -> [f32; 3]) -> Vec<f32> {
+		self.basis.samples(start, end, technique, special, evaluate)
+	}
+
+	/// Approximate the curve between `start` and `end` with a polyline
+	pub fn polyline(&self, start: f32, end: f32, technique: &CurveTechnique, special: &[f32]) -> Vec<[f32; 3]> {
+		self.samples(start, end, technique, special, &|t| self.point(t))
+			.into_iter()
+			.map(|t| self.point(t))
+			.collect()
+	}
+}
+
+/// Sum control points weighted by their basis function values. Rational points are also weighted
+/// by their own weight and the result projected back from homogeneous coordinates.
+fn combine<I>(terms: I, rational: bool) -> [f32; 3] where I: Iterator<Item = (f32, [f32; 4])> {
+	let mut sum = [ 0.0; 4 ];
+
+	for (value, point) in terms {
+		let weight = if rational { value * point[3] } else { value };
+
+		for axis in 0..3 {
+			sum[axis] += weight * point[axis];
+		}
+
+		sum[3] += weight;
+	}
+
+	if rational && sum[3] != 0.0 {
+		[ sum[0] / sum[3], sum[1] / sum[3], sum[2] / sum[3] ]
+	} else {
+		[ sum[0], sum[1], sum[2] ]
+	}
+}
+
+/// A surface ready to be evaluated. Control points are `[x, y, z, weight]` with u varying fastest.
+pub struct SurfaceEvaluator<'a> {
+	u: BasisFunctions<'a>,
+	v: BasisFunctions<'a>,
+	points: Vec<[f32; 4]>,
+	normals: Option<Vec<[f32; 3]>>,
+	rational: bool,
+}
+
+impl<'a> SurfaceEvaluator<'a> {
+	/// `size` is the number of control points in the u and v directions. Normals given with the
+	/// control points are interpolated in place of the true surface normal.
+	pub fn new(basis_type: BasisType, bases: (&'a Basis, &'a Basis), size: (usize, usize), rational: bool, points: Vec<[f32; 4]>, normals: Option<Vec<[f32; 3]>>) -> SurfaceEvaluator<'a> {
+		SurfaceEvaluator {
+			u: BasisFunctions::new(basis_type, bases.0, size.0),
+			v: BasisFunctions::new(basis_type, bases.1, size.1),
+			points,
+			normals,
+			rational,
+		}
+	}
+
+	pub fn point(&self, u: f32, v: f32) -> [f32; 3] {
+		let (first_u, values_u) = self.u.evaluate(u);
+		let (first_v, values_v) = self.v.evaluate(v);
+		let columns = self.u.count;
+
+		let terms = values_v.iter().enumerate().flat_map(|(j, &value_v)| {
+			values_u.iter().enumerate().map(move |(i, &value_u)| {
+				(value_u * value_v, self.points[(first_v + j) * columns + first_u + i])
+			})
+		});
+
+		combine(terms, self.rational)
+	}
+
+	/// Unit normal at a point, facing the side where u increases to the right and v upwards. Zero
+	/// where the surface is degenerate.
+	pub fn normal(&self, u: f32, v: f32) -> [f32; 3] {
+		if let Some(ref normals) = self.normals {
+			let (first_u, values_u) = self.u.evaluate(u);
+			let (first_v, values_v) = self.v.evaluate(v);
+			let columns = self.u.count;
+			let mut sum = [ 0.0; 3 ];
+
+			for (j, &value_v) in values_v.iter().enumerate() {
+				for (i, &value_u) in values_u.iter().enumerate() {
+					let normal = normals[(first_v + j) * columns + first_u + i];
+
+					for axis in 0..3 {
+						sum[axis] += value_u * value_v * normal[axis];
+					}
+				}
+			}
+
+			return normalize(sum);
+		}
+
+		// Central differences, one sided at the edges of the domain
+		let difference = |(low, high): (f32, f32), t: f32| {
+			let h = (high - low) * 1e-4;
+
+			((t - h).max(low), (t + h).min(high))
+		};
+
+		let (u0, u1) = difference(self.u.domain(), u);
+		let (v0, v1) = difference(self.v.domain(), v);
+
+		let du = sub(self.point(u1, v), self.point(u0, v));
+		let dv = sub(self.point(u, v1), self.point(u, v0));
+
+		normalize(cross(du, dv))
+	}
+
+	/// Grid lines in each direction approximating the surface over its parameter range
+	pub fn grid(&self, range_u: (f32, f32), range_v: (f32, f32), technique: &SurfaceTechnique, special: &[[f32; 2]]) -> (Vec<f32>, Vec<f32>) {
+		let special_u: Vec<f32> = special.iter().map(|p| p[0]).collect();
+		let special_v: Vec<f32> = special.iter().map(|p| p[1]).collect();
+		let flat = |_| [ 0.0; 3 ];
+
+		let isolines = |technique: CurveTechnique| {
+			// Subdivide along isoparametric lines at both edges and the middle of the other
+			// direction, and use every line any of them needs
+			let mut us = Vec::new();
+			let mut vs = Vec::new();
+
+			for &f in [ 0.0, 0.5, 1.0 ].iter() {
+				let v = lerp(range_v.0, range_v.1, f);
+				let u = lerp(range_u.0, range_u.1, f);
+
+				us = merge_samples(us, self.u.samples(range_u.0, range_u.1, &technique, &special_u, &|t| self.point(t, v)), range_u.1 - range_u.0);
+				vs = merge_samples(vs, self.v.samples(range_v.0, range_v.1, &technique, &special_v, &|t| self.point(u, t)), range_v.1 - range_v.0);
+			}
+
+			(us, vs)
+		};
+
+		let (us, vs) = match *technique {
+			SurfaceTechnique::Parametric { u_resolution, v_resolution } => (
+				self.u.samples(range_u.0, range_u.1, &CurveTechnique::Parametric { resolution: u_resolution }, &special_u, &flat),
+				self.v.samples(range_v.0, range_v.1, &CurveTechnique::Parametric { resolution: v_resolution }, &special_v, &flat),
+			),
+			SurfaceTechnique::ParametricRefined { resolution } => (
+				self.u.samples(range_u.0, range_u.1, &CurveTechnique::Parametric { resolution }, &special_u, &flat),
+				self.v.samples(range_v.0, range_v.1, &CurveTechnique::Parametric { resolution }, &special_v, &flat),
+			),
+			SurfaceTechnique::Spatial { max_length } => isolines(CurveTechnique::Spatial { max_length }),
+			SurfaceTechnique::Curvature { max_distance, max_angle } => isolines(CurveTechnique::Curvature { max_distance, max_angle }),
+		};
+
+		// The grid always runs in increasing parameter order
+		(merge_samples(us, Vec::new(), range_u.1 - range_u.0), merge_samples(vs, Vec::new(), range_v.1 - range_v.0))
+	}
+}
+
+/// A region of a surface kept by trimming, as loops in its parameter space
+pub struct Region {
+	pub outer: Vec<[f32; 2]>,
+	pub holes: Vec<Vec<[f32; 2]>>,
+}
+
+/// A span of a 2D curve making up part of a trimming loop
+pub struct TrimSpan<'a> {
+	pub curve: CurveEvaluator<'a>,
+	pub start: f32,
+	pub end: f32,
+	/// Parameter values of special points on the curve
+	pub special: Vec<f32>,
+	/// Samples shared with connected surfaces, which replace the curve's own samples over the
+	/// parameter range they cover
+	pub shared: Vec<&'a [(f32, [f32; 2])]>,
+}
+
+/// Approximate a loop of 2D curve spans with a polygon in the parameter space of a surface
+pub fn trim_loop(spans: &[TrimSpan], technique: &CurveTechnique, surface: &SurfaceEvaluator) -> Vec<[f32; 2]> {
+	let mut polygon: Vec<[f32; 2]> = Vec::new();
+
+	for span in spans {
+		let on_surface = |t| {
+			let p = span.curve.point(t);
+
+			surface.point(p[0], p[1])
+		};
+
+		let covered = |t: f32, samples: &[(f32, [f32; 2])]| {
+			let (first, last) = (samples[0].0, samples[samples.len() - 1].0);
+
+			first.min(last) <= t && t <= first.max(last)
+		};
+
+		let mut samples: Vec<(f32, [f32; 2])> = span.curve.samples(span.start, span.end, technique, &span.special, &on_surface)
+			.into_iter()
+			.filter(|&t| !span.shared.iter().any(|shared| covered(t, shared)))
+			.map(|t| {
+				let p = span.curve.point(t);
+
+				(t, [ p[0], p[1] ])
+			})
+			.collect();
+
+		let (low, high) = (span.start.min(span.end), span.start.max(span.end));
+
+		for shared in span.shared.iter() {
+			samples.extend(shared.iter().filter(|&&(t, _)| low <= t && t <= high));
+		}
+
+		samples.sort_by(|a, b| {
+			let order = a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal);
+
+			if span.end < span.start { order.reverse() } else { order }
+		});
+
+		for (_, p) in samples {
+			if polygon.last() != Some(&p) {
+				polygon.push(p);
+			}
+		}
+	}
+
+	if polygon.len() > 1 && polygon.first() == polygon.last() {
+		polygon.pop();
+	}
+
+	polygon
+}
+
+/// One side of a connection between surfaces: a span of a trimming curve and the surface it
+/// trims, with the grid the surface is tessellated along
+pub struct SeamSide<'a> {
+	pub surface: &'a SurfaceEvaluator<'a>,
+	pub grid: &'a (Vec<f32>, Vec<f32>),
+	pub curve: &'a CurveEvaluator<'a>,
+	pub start: f32,
+	pub end: f32,
+	pub special: &'a [f32],
+	pub technique: &'a CurveTechnique,
+}
+
+/// Samples along a connection that both surfaces use, so their tessellations meet without cracks
+#[derive(Debug)]
+pub struct Seam {
+	/// Parameter value on each side's curve and the point in its surface's parameter space for
+	/// every sample
+	pub sides: [Vec<(f32, [f32; 2])>; 2],
+	/// Position in space of every sample, shared by both surfaces
+	pub positions: Vec<[f32; 3]>,
+}
+
+/// Sample the two sides of a connection together. `None` if either side is empty.
+///
+/// Samples either curve needs are used on both, matched by how far along its span they are. Where
+/// the polyline on one side crosses a grid line of its surface a sample is added exactly on the
+/// line, so clipping to the grid adds no vertices to the seam that the other side lacks.
+pub fn seam(sides: [&SeamSide; 2]) -> Option<Seam> {
+	if sides.iter().any(|side| side.start == side.end) {
+		return None;
+	}
+
+	let point = |side: &SeamSide, f: f32| {
+		let p = side.curve.point(lerp(side.start, side.end, f));
+
+		[ p[0], p[1] ]
+	};
+
+	let mut fractions = vec![ 0.0, 1.0 ];
+
+	for side in sides.iter() {
+		let on_surface = |t| {
+			let p = side.curve.point(t);
+
+			side.surface.point(p[0], p[1])
+		};
+
+		let samples = side.curve.samples(side.start.min(side.end), side.start.max(side.end), side.technique, side.special, &on_surface)
+			.into_iter()
+			.map(|t| (t - side.start) / (side.end - side.start))
+			.filter(|&f| (0.0..=1.0).contains(&f))
+			.collect();
+
+		fractions = merge_samples(fractions, samples, 1.0);
+	}
+
+	let mut samples: Vec<(f32, [[f32; 2]; 2])> = vec![ (0.0, [ point(sides[0], 0.0), point(sides[1], 0.0) ]) ];
+
+	for &f in fractions[1..].iter() {
+		let (from, mut to) = (samples[samples.len() - 1], (f, [ point(sides[0], f), point(sides[1], f) ]));
+		let mut crossings: Vec<(f32, usize, usize, f32)> = Vec::new();
+
+		for (k, side) in sides.iter().enumerate() {
+			for (axis, lines) in [ &side.grid.0, &side.grid.1 ].iter().enumerate() {
+				let (a, b) = (from.1[k][axis], to.1[k][axis]);
+
+				for &line in lines.iter().filter(|&&line| (a < line && line < b) || (b < line && line < a)) {
+					crossings.push(((line - a) / (b - a), k, axis, line));
+				}
+			}
+		}
+
+		crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+
+		let mut inserted: Vec<(f32, [[f32; 2]; 2])> = Vec::new();
+
+		for (g, k, axis, line) in crossings {
+			// Crossings at almost the same place become one sample, on every line involved
+			if g >= 1.0 - 1e-4 {
+				to.1[k][axis] = line;
+				continue;
+			}
+
+			match inserted.last_mut() {
+				Some(last) if g - last.0 <= 1e-4 => {
+					last.1[k][axis] = line;
+					continue;
+				},
+				_ => (),
+			}
+
+			if g <= 1e-4 {
+				let last = samples.len() - 1;
+
+				samples[last].1[k][axis] = line;
+				continue;
+			}
+
+			let mut points = [ lerp2(from.1[0], to.1[0], g), lerp2(from.1[1], to.1[1], g) ];
+
+			points[k][axis] = line;
+			inserted.push((g, points));
+		}
+
+		samples.extend(inserted.into_iter().map(|(g, points)| (lerp(from.0, to.0, g), points)));
+		samples.push(to);
+	}
+
+	let positions = samples.iter()
+		.map(|&(_, points)| {
+			let first = sides[0].surface.point(points[0][0], points[0][1]);
+			let second = sides[1].surface.point(points[1][0], points[1][1]);
+
+			lerp3(first, second, 0.5)
+		})
+		.collect();
+
+	let side = |k: usize| samples.iter()
+		.map(|&(f, points)| (lerp(sides[k].start, sides[k].end, f), points[k]))
+		.collect();
+
+	Some(Seam { sides: [ side(0), side(1) ], positions })
+}
+
+/// Positions in space fixed for points in a surface's parameter space, as from seams, keyed by the
+/// bits of the point's coordinates
+pub type SharedPositions = HashMap<(u32, u32), [f32; 3]>;
+
+pub fn shared_key(p: [f32; 2]) -> (u32, u32) {
+	(p[0].to_bits(), p[1].to_bits())
+}
+
+/// Triangulate a surface into a triangle list along the lines of a grid from `SurfaceEvaluator::grid`.
+///
+/// Without trimming regions every cell becomes two triangles. Otherwise cells wholly inside a
+/// region are kept, cells crossed by a loop are clipped to the region and triangulated, and the
+/// rest are dropped. Loop points with a shared position, as on a seam, are placed there.
+pub fn tessellate_surface(surface: &SurfaceEvaluator, grid: &(Vec<f32>, Vec<f32>), regions: &[Region], shared: &SharedPositions) -> Vec<BufferVertex> {
+	let (ref us, ref vs) = *grid;
+	let mut triangles = Vec::new();
+
+	if us.len() < 2 || vs.len() < 2 {
+		return triangles;
+	}
+
+	let vertex = |u: f32, v: f32| BufferVertex { position: surface.point(u, v), normal: surface.normal(u, v) };
+
+	let corners: Vec<BufferVertex> = vs.iter()
+		.flat_map(|&v| us.iter().map(move |&u| (u, v)))
+		.map(|(u, v)| vertex(u, v))
+		.collect();
+
+	let corner = |i: usize, j: usize| corners[j * us.len() + i];
+
+	// Each region is triangulated once; cells crossed by its boundary clip those (convex)
+	// triangles, which unlike clipping the concave region itself never folds back on itself
+	let polygons: Vec<_> = regions.iter()
+		.map(|region| merge_holes(&region.outer, &region.holes))
+		.filter(|polygon| polygon.len() >= 3)
+		.map(|polygon| {
+			let pieces: Vec<[[f32; 2]; 3]> = triangulate_2d(&polygon).into_iter()
+				.map(|triangle| [ polygon[triangle[0]], polygon[triangle[1]], polygon[triangle[2]] ])
+				.collect();
+
+			(polygon, pieces)
+		})
+		.collect();
+
+	for j in 0..vs.len() - 1 {
+		for i in 0..us.len() - 1 {
+			let (min, max) = ([ us[i], vs[j] ], [ us[i + 1], vs[j + 1] ]);
+			let quad = [ corner(i, j), corner(i + 1, j), corner(i + 1, j + 1), corner(i, j + 1) ];
+
+			if regions.is_empty() {
+				push_triangle(&mut triangles, [ quad[0], quad[1], quad[2] ]);
+				push_triangle(&mut triangles, [ quad[0], quad[2], quad[3] ]);
+				continue;
+			}
+
+			let center = [ (min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0 ];
+
+			let clipped_vertex = |p: [f32; 2]| {
+				if let Some(&position) = shared.get(&shared_key(p)) {
+					return BufferVertex { position, normal: surface.normal(p[0], p[1]) };
+				}
+
+				// Points on the border of the cell go on the straight edge between its corners,
+				// where a neighbouring cell that is kept whole meets it
+				let f = [ (p[0] - min[0]) / (max[0] - min[0]), (p[1] - min[1]) / (max[1] - min[1]) ];
+
+				let edge = if p[0] == min[0] {
+					Some((quad[0], quad[3], f[1]))
+				} else if p[0] == max[0] {
+					Some((quad[1], quad[2], f[1]))
+				} else if p[1] == min[1] {
+					Some((quad[0], quad[1], f[0]))
+				} else if p[1] == max[1] {
+					Some((quad[3], quad[2], f[0]))
+				} else {
+					None
+				};
+
+				match edge {
+					Some((a, b, f)) => BufferVertex {
+						position: lerp3(a.position, b.position, f),
+						normal: normalize(lerp3(a.normal, b.normal, f)),
+					},
+					None => vertex(p[0], p[1]),
+				}
+			};
+
+			for (polygon, pieces) in polygons.iter() {
+				if !crosses_rect(polygon, min, max) {
+					if contains(polygon, center) {
+						push_triangle(&mut triangles, [ quad[0], quad[1], quad[2] ]);
+						push_triangle(&mut triangles, [ quad[0], quad[2], quad[3] ]);
+					}
+
+					continue;
+				}
+
+				for piece in pieces.iter().filter(|piece| overlaps_rect(&piece[..], min, max)) {
+					let clipped = clip_to_rect(&piece[..], min, max);
+
+					for k in 2..clipped.len() {
+						let (a, b, c) = (clipped[0], clipped[k - 1], clipped[k]);
+
+						// Pieces touching the cell along an edge or corner clip to slivers with no area
+						if (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]) <= f32::EPSILON * (max[0] - min[0]) * (max[1] - min[1]) {
+							continue;
+						}
+
+						push_triangle(&mut triangles, [ clipped_vertex(a), clipped_vertex(b), clipped_vertex(c) ]);
+					}
+				}
+			}
+		}
+	}
+
+	triangles
+}
+
+/// Whether the bounding box of `points` overlaps the rectangle from `min` to `max`
+fn overlaps_rect(points: &[[f32; 2]], min: [f32; 2], max: [f32; 2]) -> bool {
+	(0..2).all(|axis| {
+		points.iter().any(|point| point[axis] >= min[axis]) && points.iter().any(|point| point[axis] <= max[axis])
+	})
+}
+
+/// Add a triangle, giving vertices where the surface normal is degenerate the triangle's normal
+fn push_triangle(triangles: &mut Vec<BufferVertex>, mut corners: [BufferVertex; 3]) {
+	if corners.iter().any(|corner| corner.normal == [ 0.0; 3 ]) {
+		let flat = normalize(cross(
+			sub(corners[1].position, corners[0].position),
+			sub(corners[2].position, corners[0].position),
+		));
+
+		for corner in corners.iter_mut().filter(|corner| corner.normal == [ 0.0; 3 ]) {
+			corner.normal = flat;
+		}
+	}
+
+	triangles.extend_from_slice(&corners);
+}
+
+fn lerp(a: f32, b: f32, f: f32) -> f32 {
+	a + (b - a) * f
+}
+
+fn lerp2(a: [f32; 2], b: [f32; 2], f: f32) -> [f32; 2] {
+	[ lerp(a[0], b[0], f), lerp(a[1], b[1], f) ]
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], f: f32) -> [f32; 3] {
+	[ lerp(a[0], b[0], f), lerp(a[1], b[1], f), lerp(a[2], b[2], f) ]
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+	dot(sub(a, b), sub(a, b)).sqrt()
+}
+
+/// Angle between two vectors in degrees, or zero if either has no length
+fn angle(a: [f32; 3], b: [f32; 3]) -> f32 {
+	let lengths = dot(a, a).sqrt() * dot(b, b).sqrt();
+
+	if lengths > 0.0 {
+		(dot(a, b) / lengths).clamp(-1.0, 1.0).acos().to_degrees()
+	} else {
+		0.0
+	}
+}
+
+/// Distance from a point to the segment from `a` to `b`
+fn segment_distance(p: [f32; 3], a: [f32; 3], b: [f32; 3]) -> f32 {
+	let ab = sub(b, a);
+	let length = dot(ab, ab);
+
+	let t = if length > 0.0 { (dot(sub(p, a), ab) / length).clamp(0.0, 1.0) } else { 0.0 };
+
+	distance(p, [ a[0] + ab[0] * t, a[1] + ab[1] * t, a[2] + ab[2] * t ])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::testing::assert_close;
+	use super::super::obj::{ self, WavefrontModel };
+	use std::env;
+	use std::fs;
+	use std::process;
+
+	fn basis(degree: u32, parameters: &[f32]) -> Basis {
+		Basis { degree, matrix: None, step: None, parameters: parameters.to_vec() }
+	}
+
+	fn points(positions: &[[f32; 3]]) -> Vec<[f32; 4]> {
+		positions.iter().map(|p| [ p[0], p[1], p[2], 1.0 ]).collect()
+	}
+
+	/// Load an OBJ file with the given contents from a temporary file
+	fn load(name: &str, contents: &str) -> WavefrontModel {
+		let path = env::temp_dir().join(format!("obj-rs-{}-{}.obj", name, process::id()));
+
+		fs::write(&path, contents).unwrap();
+
+		let model = obj::load(path.to_str().unwrap());
+
+		fs::remove_file(&path).unwrap();
+
+		model.unwrap()
+	}
+
+	/// Total area of a triangle list
+	fn area(triangles: &[BufferVertex]) -> f32 {
+		triangles.chunks(3)
+			.map(|t| {
+				let n = cross(sub(t[1].position, t[0].position), sub(t[2].position, t[0].position));
+
+				dot(n, n).sqrt() / 2.0
+			})
+			.sum()
+	}
+
+	const CONTROL: [[f32; 3]; 6] = [
+		[ 0.0, 0.0, 0.0 ],
+		[ 1.0, 2.0, 0.0 ],
+		[ 2.0, -1.0, 1.0 ],
+		[ 3.0, 1.0, 0.0 ],
+		[ 4.0, 0.0, 2.0 ],
+		[ 5.0, 3.0, 1.0 ],
+	];
+
+	#[test]
+	fn bezier_endpoints() {
+		let bezier = basis(3, &[ 0.0, 1.0 ]);
+		let curve = CurveEvaluator::new(BasisType::Bezier, &bezier, false, points(&CONTROL[..4]));
+
+		assert_close(curve.point(0.0), CONTROL[0], 1e-5);
+		assert_close(curve.point(1.0), CONTROL[3], 1e-5);
+
+		// Two segments meet at the shared control point
+		let bezier = basis(2, &[ 0.0, 1.0, 3.0 ]);
+		let curve = CurveEvaluator::new(BasisType::Bezier, &bezier, false, points(&CONTROL[..5]));
+
+		assert_close(curve.point(0.0), CONTROL[0], 1e-5);
+		assert_close(curve.point(1.0), CONTROL[2], 1e-5);
+		assert_close(curve.point(3.0), CONTROL[4], 1e-5);
+	}
+
+	#[test]
+	fn clamped_bspline_endpoints() {
+		let knots = basis(3, &[ 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 3.0, 3.0, 3.0 ]);
+		let curve = CurveEvaluator::new(BasisType::BSpline, &knots, false, points(&CONTROL));
+
+		assert_eq!(curve.basis.domain(), (0.0, 3.0));
+		assert_close(curve.point(0.0), CONTROL[0], 1e-5);
+		assert_close(curve.point(3.0), CONTROL[5], 1e-5);
+
+		// Rational curves with unequal weights still start and end on their control points
+		let mut weighted = points(&CONTROL);
+
+		weighted[0][3] = 3.0;
+		weighted[5][3] = 0.5;
+
+		let curve = CurveEvaluator::new(BasisType::BSpline, &knots, true, weighted);
+
+		assert_close(curve.point(0.0), CONTROL[0], 1e-5);
+		assert_close(curve.point(3.0), CONTROL[5], 1e-5);
+	}
+
+	#[test]
+	fn curve_polyline_by_index() {
+		let model = load("curves", "\
+v 0 0 0
+v 1 2 0
+v 2 -1 1
+v 3 1 0
+cstype bezier
+deg 1
+curv 0 1 1 2
+parm u 0 1
+end
+deg 3
+o second
+curv 0 1 1 2 3 4
+parm u 0 1
+end
+");
+
+		// Indices count the curves of every object
+		let polyline = model.curve_polyline(1).unwrap();
+
+		assert_close(polyline[0], CONTROL[0], 1e-5);
+		assert_close(polyline[polyline.len() - 1], CONTROL[3], 1e-5);
+		let polyline = model.curve_polyline(0).unwrap();
+
+		assert_close(polyline[0], CONTROL[0], 1e-5);
+		assert_close(polyline[polyline.len() - 1], CONTROL[1], 1e-5);
+		assert!(model.curve_polyline(2).is_none());
+	}
+
+	#[test]
+	fn partition_of_unity() {
+		let cases = [
+			(BasisType::Bezier, basis(3, &[ 0.0, 1.0, 2.5 ]), 7),
+			(BasisType::BSpline, basis(3, &[ 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 3.0, 3.0, 3.0 ]), 6),
+			(BasisType::BSpline, basis(2, &[ 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0 ]), 5),
+			(BasisType::BSpline, basis(3, &[ 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0 ]), 6),
+			(BasisType::Cardinal, basis(3, &[ 0.0, 1.0, 2.0 ]), 5),
+		];
+
+		for &(basis_type, ref basis, count) in cases.iter() {
+			let functions = BasisFunctions::new(basis_type, basis, count);
+			let (start, end) = functions.domain();
+
+			for step in 0..=100 {
+				let t = start + (end - start) * step as f32 / 100.0;
+				let (first, values) = functions.evaluate(t);
+
+				assert!(first + values.len() <= count, "{:?} at {}", basis_type, t);
+				assert!((values.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{:?} at {}: {:?}", basis_type, t, values);
+
+				if basis_type != BasisType::Cardinal {
+					assert!(values.iter().all(|&value| value >= -1e-6), "{:?} at {}: {:?}", basis_type, t, values);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn trimmed_surface_area() {
+		// A unit square surface with a square hole 0.4 across, and the same trimmed to a square
+		// 0.8 across
+		let square = |first: usize, p: [f32; 2]| format!(
+			"vp {0} {0}\nvp {1} {0}\nvp {1} {1}\nvp {0} {1}\ncurv2 {2} {3} {4} {5} {2}\nparm u 0 1 2 3 4\nend\n",
+			p[0], p[1], first, first + 1, first + 2, first + 3
+		);
+
+		let header = format!(
+			"cstype bezier\ndeg 1\n{}{}v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\ndeg 1 1\nstech cparma 2 2\n",
+			square(1, [ 0.3, 0.7 ]), square(5, [ 0.1, 0.9 ])
+		);
+
+		let surface = "surf 0 1 0 1 1 2 3 4\nparm u 0 1\nparm v 0 1\n";
+
+		let model = load("hole", &format!("{}{}hole 0 4 1\nend\n", header, surface));
+
+		assert!((area(&model.surface_triangles(0).unwrap()) - 0.84).abs() < 1e-4);
+
+		let model = load("trim", &format!("{}{}trim 0 4 2\nhole 0 4 1\nend\n", header, surface));
+
+		assert!((area(&model.surface_triangles(0).unwrap()) - 0.48).abs() < 1e-4);
+	}
+
+	#[test]
+	fn connected_surfaces_share_seam_vertices() {
+		// Two biquadratic patches tessellated at different resolutions, meeting along x = 1
+		let model = load("con", "\
+vp 0 0
+vp 1 0
+vp 1 1
+vp 0 1
+cstype bezier
+deg 1
+curv2 1 2 3 4 1
+parm u 0 1 2 3 4
+end
+v 0 0 0
+v 0.5 0 0.5
+v 1 0 0
+v 0 0.5 0.3
+v 0.5 0.5 1
+v 1 0.5 0.4
+v 0 1 0
+v 0.5 1 0.5
+v 1 1 0
+v 1.5 0 -0.5
+v 2 0 0
+v 1.5 0.5 0.7
+v 2 0.5 0
+v 1.5 1 -0.5
+v 2 1 0
+deg 2 2
+stech cparma 4 4
+surf 0 1 0 1 1 2 3 4 5 6 7 8 9
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+stech cparma 7 5
+ctech cparm 9
+surf 0 1 0 1 3 10 11 6 12 13 9 14 15
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+con 1 1.0 2.0 1 2 4.0 3.0 1
+");
+
+		let seam_vertices = |index| {
+			let mut vertices: Vec<(u32, u32, u32)> = model.surface_triangles(index).unwrap().iter()
+				.map(|vertex| vertex.position)
+				.filter(|p| (p[0] - 1.0).abs() < 1e-4)
+				.map(|p| (p[0].to_bits(), p[1].to_bits(), p[2].to_bits()))
+				.collect();
+
+			vertices.sort();
+			vertices.dedup();
+			vertices
+		};
+
+		let first = seam_vertices(0);
+
+		assert!(first.len() > 2, "{:?}", first);
+		assert_eq!(first, seam_vertices(1));
+		assert!(model.surface_triangles(2).is_none());
+	}
+}
@@ -0,0 +1,307 @@
+use nom::IResult;
+use std::borrow::Cow;
+use std::io::{ self, BufRead };
+
+use super::error::{ WavefrontError, Warning, Location, syntax_error, incomplete_error, trailing_error };
+
+/// Iterator over the statements of a file, yielding the one-based line number each starts on and
+/// its text including the terminator.
+///
+/// A line ending in `\` continues on the next line. The backslash and line ending are replaced
+/// with a space, so a statement spanning several lines is parsed as one. A `\` on the last line
+/// of the input is dropped. `locate` maps locations in the joined text back to the lines they
+/// came from.
+///
+/// The statement parsers expect every statement to end in `\n` straight after its last value, so
+/// trailing comments, whitespace and carriage returns are removed and a final line without a line
+/// ending gets one. Lines that need this are returned as owned copies.
+pub struct Lines<'a> {
+	input: &'a [u8],
+	line_number: usize,
+	continued: Vec<Continued>,
+}
+
+/// A statement that spans several lines
+struct Continued {
+	/// Line the statement starts on
+	line_number: usize,
+	/// Offset in the joined text of the start of each line after the first
+	starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+	pub fn new(input: &'a [u8]) -> Lines<'a> {
+		Lines::starting_at(input, 1)
+	}
+
+	/// Lines of part of a file, numbered from the line the part starts on
+	pub fn starting_at(input: &'a [u8], line_number: usize) -> Lines<'a> {
+		Lines {
+			input,
+			line_number: line_number - 1,
+			continued: Vec::new(),
+		}
+	}
+
+	/// Point an error at the line and column it came from in a statement that was continued over
+	/// several lines, showing the text of just that line
+	pub fn locate(&self, mut error: WavefrontError) -> WavefrontError {
+		if let Some(location) = error.location_mut() {
+			self.relocate(location);
+		}
+
+		error
+	}
+
+	/// Like `locate`, for a warning
+	pub fn locate_warning(&self, mut warning: Warning) -> Warning {
+		self.relocate(&mut warning.location);
+
+		warning
+	}
+
+	fn relocate(&self, location: &mut Location) {
+		if let Some(continued) = self.continued.iter().find(|continued| continued.line_number == location.line) {
+			let offset = location.column.saturating_sub(1);
+			let line = continued.starts.iter().take_while(|&&start| start <= offset).count();
+
+			// Each line runs up to the space its continuation was replaced with
+			let start = if line == 0 { 0 } else { continued.starts[line - 1] };
+			let end = continued.starts.get(line).map_or(location.text.len(), |&next| next - 1);
+
+			if let Some(text) = location.text.get(start..end) {
+				let text = if line < continued.starts.len() { format!("{}\\", text) } else { String::from(text) };
+
+				location.line += line;
+				location.column -= start;
+				location.text = text;
+			}
+		}
+	}
+
+	/// Take the next physical line, with a line ending appended if it has none
+	fn next_line(&mut self) -> Cow<'a, [u8]> {
+		self.line_number += 1;
+
+		match self.input.iter().position(|&c| c == b'\n') {
+			Some(end) => {
+				let (line, rest) = self.input.split_at(end + 1);
+
+				self.input = rest;
+
+				Cow::Borrowed(line)
+			},
+			None => {
+				let mut line = self.input.to_vec();
+
+				line.push(b'\n');
+				self.input = &self.input[self.input.len()..];
+
+				Cow::Owned(line)
+			}
+		}
+	}
+}
+
+/// Read lines from a stream into `block`, replacing its contents, until it holds at least `size`
+/// bytes or the stream ends. A statement continued over several lines is never split between
+/// blocks. Returns the number of lines read, which is zero at the end of the stream.
+pub fn read_block<R: BufRead>(reader: &mut R, block: &mut Vec<u8>, size: usize) -> io::Result<usize> {
+	let mut lines = 0;
+
+	block.clear();
+
+	loop {
+		let start = block.len();
+
+		if reader.read_until(b'\n', block)? == 0 {
+			return Ok(lines);
+		}
+
+		lines += 1;
+
+		if block.len() >= size && block.ends_with(b"\n") && continuation(&block[start..]).is_none() {
+			return Ok(lines);
+		}
+	}
+}
+
+/// Split input into `count` chunks of whole lines of about the same size, with the line number
+/// each starts on. A statement continued over several lines is never split between chunks.
+pub fn split(input: &[u8], count: usize) -> Vec<(usize, &[u8])> {
+	let size = input.len() / count.max(1) + 1;
+	let mut chunks = Vec::with_capacity(count);
+	let mut rest = input;
+	let mut line_number = 1;
+
+	while !rest.is_empty() {
+		let mut end = size.min(rest.len());
+
+		while end < rest.len() && (rest[end - 1] != b'\n' || continuation(&rest[..end]).is_some()) {
+			end += 1;
+		}
+
+		let (chunk, next) = rest.split_at(end);
+
+		chunks.push((line_number, chunk));
+		line_number += chunk.iter().filter(|&&c| c == b'\n').count();
+		rest = next;
+	}
+
+	chunks
+}
+
+/// Length of a line before a trailing `\` that continues it, if it has one
+fn continuation(line: &[u8]) -> Option<usize> {
+	let content = line.len() - if line.ends_with(b"\r\n") { 2 } else { 1 };
+
+	if content > 0 && line[content - 1] == b'\\' {
+		Some(content - 1)
+	} else {
+		None
+	}
+}
+
+fn is_space(c: u8) -> bool {
+	c == b' ' || c == b'\t' || c == b'\r' || c == b'\n'
+}
+
+/// Strip a trailing comment and whitespace from a line, leaving it ending in `\n`. A `#` only
+/// starts a comment at the start of the line or after whitespace, so names containing one are
+/// kept whole.
+fn trim_end(line: Cow<[u8]>) -> Cow<[u8]> {
+	let comment = (0..line.len())
+		.find(|&i| line[i] == b'#' && (i == 0 || is_space(line[i - 1])))
+		.unwrap_or(line.len());
+
+	let end = line[..comment].iter().rposition(|&c| !is_space(c)).map_or(0, |last| last + 1);
+
+	if line.len() == end + 1 && line[end] == b'\n' {
+		return line;
+	}
+
+	let mut trimmed = line[..end].to_vec();
+
+	trimmed.push(b'\n');
+
+	Cow::Owned(trimmed)
+}
+
+/// Split the keyword off the start of a statement, skipping any indentation. Returns the keyword
+/// and the statement from the keyword onwards.
+pub fn keyword(line: &[u8]) -> (&[u8], &[u8]) {
+	let start = line.iter().position(|&c| c != b' ' && c != b'\t').unwrap_or(line.len());
+	let statement = &line[start..];
+	let end = statement.iter().position(|&c| is_space(c)).unwrap_or(statement.len());
+
+	(&statement[..end], statement)
+}
+
+impl<'a> Iterator for Lines<'a> {
+	type Item = (usize, Cow<'a, [u8]>);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.input.is_empty() {
+			return None;
+		}
+
+		let mut line = self.next_line();
+		let line_number = self.line_number;
+
+		if continuation(&line).is_none() {
+			return Some((line_number, trim_end(line)));
+		}
+
+		let mut joined = Vec::with_capacity(line.len() * 2);
+		let mut starts = Vec::new();
+
+		loop {
+			match continuation(&line) {
+				Some(content) if !self.input.is_empty() => {
+					joined.extend_from_slice(&line[..content]);
+					joined.push(b' ');
+					starts.push(joined.len());
+
+					line = self.next_line();
+				},
+				// The last line of the file has nothing to continue onto, so the statement ends there
+				Some(content) => {
+					joined.extend_from_slice(&line[..content]);
+					joined.push(b'\n');
+					break;
+				},
+				None => {
+					joined.extend_from_slice(&line);
+					break;
+				},
+			}
+		}
+
+		if !starts.is_empty() {
+			self.continued.push(Continued { line_number, starts });
+		}
+
+		Some((line_number, trim_end(Cow::Owned(joined))))
+	}
+}
+
+/// Run a statement parser over a single line, converting nom failures into errors that point at
+/// the offending line and column. The whole line must be parsed, so values the parser doesn't
+/// expect are reported rather than dropped.
+pub fn parse_line<O>(parser: fn(&[u8]) -> IResult<&[u8], O>, line_number: usize, line: &[u8]) -> Result<O, WavefrontError> {
+	match parser(line) {
+		IResult::Done(rest, output) => if rest.is_empty() {
+			Ok(output)
+		} else {
+			Err(trailing_error(line_number, line, rest))
+		},
+		IResult::Incomplete(_) => Err(incomplete_error(line_number, line)),
+		IResult::Error(err) => Err(syntax_error(line_number, line, err)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn statements(input: &str) -> Vec<(usize, String)> {
+		Lines::new(input.as_bytes())
+			.map(|(line_number, line)| (line_number, String::from_utf8(line.into_owned()).unwrap()))
+			.collect()
+	}
+
+	#[test]
+	fn continued_lines_are_joined() {
+		assert_eq!(statements("f 1 2 \\\n  3\nv 0 0 0\n"), vec![
+			(1, String::from("f 1 2    3\n")),
+			(3, String::from("v 0 0 0\n")),
+		]);
+	}
+
+	#[test]
+	fn continuation_across_crlf() {
+		assert_eq!(statements("f 1 2 \\\r\n3\r\nv 0 0 0\r\n"), vec![
+			(1, String::from("f 1 2  3\n")),
+			(3, String::from("v 0 0 0\n")),
+		]);
+	}
+
+	#[test]
+	fn continuation_at_end_of_input() {
+		assert_eq!(statements("f 1 2 3 \\"), vec![ (1, String::from("f 1 2 3\n")) ]);
+		assert_eq!(statements("f 1 2 3 \\\n"), vec![ (1, String::from("f 1 2 3\n")) ]);
+		assert_eq!(statements("f 1 2 \\\r\n3 \\\r\n"), vec![ (1, String::from("f 1 2  3\n")) ]);
+	}
+
+	#[test]
+	fn trailing_continuation_parses() {
+		use super::super::obj;
+
+		for input in &[ "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3 \\", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 \\\r\n3 \\\r\n" ] {
+			let (model, _) = obj::parse(input.as_bytes()).unwrap();
+
+			assert!(model.warnings().is_empty(), "{:?}", model.warnings());
+			assert_eq!(model.objects()[0].faces()[0].vertices().len(), 3);
+		}
+	}
+}
@@ -1,2 +1,10 @@
 pub mod obj;
-pub mod mtl;
\ No newline at end of file
+pub mod mtl;
+pub mod error;
+pub mod spectral;
+mod freeform;
+mod lines;
+mod number;
+mod triangulate;
+#[cfg(test)]
+mod testing;
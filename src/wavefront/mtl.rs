@@ -1,7 +1,12 @@
-use nom::{ space, digit, line_ending, IResult, not_line_ending };
-use nom;
+use nom::{ IResult, space, line_ending, not_line_ending };
+
+use super::number::{ parse_float, parse_unsigned };
+use super::error::{ WavefrontError, Warning, ParseMode, Location, token_column };
+use super::lines::{ self, Lines, parse_line };
+use super::spectral::{ self, Illuminant };
 use std::str;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 pub type WavefrontMaterials = HashMap<String, WavefrontMaterial>;
 
@@ -12,51 +17,502 @@ pub struct WavefrontMaterial {
 	pub ambient: [f32; 3],
 	pub diffuse: [f32; 3],
 	pub specular: [f32; 3],
+	/// Colour the material emits from `Ke`
+	pub emissive: [f32; 3],
+	/// Index of refraction from `Ni`. `1.0` means light passing through doesn't bend.
+	pub optical_density: f32,
+	/// How opaque the material is from `d`, or one minus `Tr`. `1.0` is fully opaque.
+	pub dissolve: f32,
+	/// Set by `d -halo`: the material is more opaque where it faces the viewer edge-on, by
+	/// `1 - (N . V) * (1 - dissolve)`
+	pub halo: bool,
+	/// Colour of the light allowed through the material from `Tf`
+	pub transmission_filter: [f32; 3],
+	/// Illumination model from `illum`
+	pub illumination_model: IlluminationModel,
+	/// Texture for the ambient colour from `map_Ka`
+	pub ambient_map: Option<TextureMap>,
+	/// Texture for the diffuse colour from `map_Kd`
+	pub diffuse_map: Option<TextureMap>,
+	/// Texture for the specular colour from `map_Ks`
+	pub specular_map: Option<TextureMap>,
+	/// Scalar texture for the specular exponent from `map_Ns`
+	pub specular_exponent_map: Option<TextureMap>,
+	/// Scalar texture for the dissolve from `map_d`
+	pub dissolve_map: Option<TextureMap>,
+	/// Scalar texture perturbing the surface normal from `bump` or `map_bump`
+	pub bump_map: Option<TextureMap>,
+	/// Scalar texture displacing the surface from `disp`
+	pub displacement_map: Option<TextureMap>,
+	/// Scalar texture blending between the material and its surroundings from `decal`
+	pub decal_map: Option<TextureMap>,
+	/// Reflection maps from `refl`: a single sphere map, or one for each face of a cube
+	pub reflection_maps: Vec<TextureMap>,
+	/// Properties from the PBR extension statements, if the material has any
+	pub pbr: Option<PbrMaterial>,
+}
+
+/// Physically based properties from the de-facto PBR extension to MTL written by Blender,
+/// Substance and others. The extension gives no defaults, so anything not in the file is `None`
+/// for the renderer to derive, e.g. roughness from the specular exponent.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PbrMaterial {
+	/// Surface roughness from `Pr`, `0` to `1`
+	pub roughness: Option<f32>,
+	/// How metallic the surface is from `Pm`, `0` to `1`
+	pub metallic: Option<f32>,
+	/// Sheen from `Ps`
+	pub sheen: Option<f32>,
+	/// Thickness of the clearcoat layer from `Pc`
+	pub clearcoat_thickness: Option<f32>,
+	/// Roughness of the clearcoat layer from `Pcr`
+	pub clearcoat_roughness: Option<f32>,
+	/// Anisotropy from `aniso`
+	pub anisotropy: Option<f32>,
+	/// Rotation of the anisotropy from `anisor`
+	pub anisotropy_rotation: Option<f32>,
+	/// Scalar texture for the roughness from `map_Pr`
+	pub roughness_map: Option<TextureMap>,
+	/// Scalar texture for how metallic the surface is from `map_Pm`
+	pub metallic_map: Option<TextureMap>,
+	/// Scalar texture for the sheen from `map_Ps`
+	pub sheen_map: Option<TextureMap>,
+	/// Tangent space normal map from `norm`
+	pub normal_map: Option<TextureMap>,
+	/// Texture packing roughness, metallic and ambient occlusion into its red, green and blue
+	/// channels, from `map_RMA`
+	pub rma_map: Option<TextureMap>,
+	/// Texture packing ambient occlusion, roughness and metallic into its red, green and blue
+	/// channels, from `map_ORM`
+	pub orm_map: Option<TextureMap>,
+}
+
+impl WavefrontMaterial {
+	/// A material with the defaults used for statements missing from its definition: black
+	/// colours, fully opaque, no refraction, a white transmission filter and the illumination
+	/// model with ambient, diffuse and specular terms
+	pub fn new(name: String) -> WavefrontMaterial {
+		WavefrontMaterial {
+			name,
+			specular_exponent: 0.0,
+			ambient: [ 0.0, 0.0, 0.0 ],
+			diffuse: [ 0.0, 0.0, 0.0 ],
+			specular: [ 0.0, 0.0, 0.0 ],
+			emissive: [ 0.0, 0.0, 0.0 ],
+			optical_density: 1.0,
+			dissolve: 1.0,
+			halo: false,
+			transmission_filter: [ 1.0, 1.0, 1.0 ],
+			illumination_model: IlluminationModel::default(),
+			ambient_map: None,
+			diffuse_map: None,
+			specular_map: None,
+			specular_exponent_map: None,
+			dissolve_map: None,
+			bump_map: None,
+			displacement_map: None,
+			decal_map: None,
+			reflection_maps: Vec::new(),
+			pbr: None,
+		}
+	}
+}
+
+/// How a material is lit, from `illum`. Each model adds terms to the ones before it unless noted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IlluminationModel {
+	/// `0`: the diffuse colour, unlit
+	Constant,
+	/// `1`: ambient and Lambertian diffuse lighting
+	Diffuse,
+	/// `2`: adds Blinn-Phong specular highlights
+	#[default]
+	Highlight,
+	/// `3`: adds ray traced reflections
+	Reflection,
+	/// `4`: glass, transparent but keeping its highlights, with ray traced reflections
+	Glass,
+	/// `5`: ray traced reflections weighted by the Fresnel effect
+	Fresnel,
+	/// `6`: transparency with refraction by the optical density, and ray traced reflections
+	Refraction,
+	/// `7`: transparency with refraction, and ray traced reflections weighted by the Fresnel effect
+	FresnelRefraction,
+	/// `8`: reflections from reflection maps without ray tracing
+	ReflectionMap,
+	/// `9`: glass with reflections from reflection maps without ray tracing
+	GlassReflectionMap,
+	/// `10`: invisible except for shadows cast onto it
+	ShadowMatte,
+}
+
+impl IlluminationModel {
+	/// The model numbered `number` in an `illum` statement
+	pub fn from_number(number: u32) -> Option<IlluminationModel> {
+		match number {
+			0 => Some(IlluminationModel::Constant),
+			1 => Some(IlluminationModel::Diffuse),
+			2 => Some(IlluminationModel::Highlight),
+			3 => Some(IlluminationModel::Reflection),
+			4 => Some(IlluminationModel::Glass),
+			5 => Some(IlluminationModel::Fresnel),
+			6 => Some(IlluminationModel::Refraction),
+			7 => Some(IlluminationModel::FresnelRefraction),
+			8 => Some(IlluminationModel::ReflectionMap),
+			9 => Some(IlluminationModel::GlassReflectionMap),
+			10 => Some(IlluminationModel::ShadowMatte),
+			_ => None,
+		}
+	}
+
+	/// Number of the model in an `illum` statement
+	pub fn number(self) -> u32 {
+		self as u32
+	}
+
+	/// Whether light from the scene shades the surface, as opposed to drawing the flat diffuse
+	/// colour or nothing at all
+	pub fn lit(self) -> bool {
+		!matches!(self, IlluminationModel::Constant | IlluminationModel::ShadowMatte)
+	}
+
+	/// Whether the ambient colour contributes
+	pub fn ambient(self) -> bool {
+		self.lit()
+	}
+
+	/// Whether the specular colour and exponent give highlights
+	pub fn specular(self) -> bool {
+		self.lit() && self != IlluminationModel::Diffuse
+	}
+
+	/// Whether the surface reflects its surroundings, by ray tracing or from reflection maps
+	pub fn reflection(self) -> bool {
+		self.number() >= 3 && self.number() <= 9
+	}
+
+	/// Whether reflections, and refractions if any, are ray traced rather than taken from
+	/// reflection maps
+	pub fn ray_traced(self) -> bool {
+		self.number() >= 3 && self.number() <= 7
+	}
+
+	/// Whether light passes through the surface, filtered by the transmission filter colour
+	pub fn transparency(self) -> bool {
+		matches!(self, IlluminationModel::Glass | IlluminationModel::Refraction | IlluminationModel::FresnelRefraction | IlluminationModel::GlassReflectionMap)
+	}
+
+	/// Whether light passing through bends by the optical density
+	pub fn refraction(self) -> bool {
+		matches!(self, IlluminationModel::Refraction | IlluminationModel::FresnelRefraction)
+	}
+
+	/// Whether reflections are stronger at grazing angles
+	pub fn fresnel(self) -> bool {
+		matches!(self, IlluminationModel::Fresnel | IlluminationModel::FresnelRefraction)
+	}
+}
+
+/// An image mapped onto a material, with the options given before its file name
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureMap {
+	/// File name of the image as written, relative to the material library
+	pub file: String,
+	/// Whether the texture is blended horizontally, from `-blendu`
+	pub blend_u: bool,
+	/// Whether the texture is blended vertically, from `-blendv`
+	pub blend_v: bool,
+	/// Multiplier for the values of a bump map, from `-bm`
+	pub bump_multiplier: f32,
+	/// Increase in the sharpness of mip-mapped textures, from `-boost`
+	pub boost: Option<f32>,
+	/// Whether colour correction is applied, from `-cc`
+	pub color_correction: bool,
+	/// Whether texture coordinates are clamped to 0 to 1 instead of repeating, from `-clamp`
+	pub clamp: bool,
+	/// Channel a scalar texture is read from, from `-imfchan`. `None` uses the default for the
+	/// kind of map: luminance for bump maps and matte for decals.
+	pub channel: Option<TextureChannel>,
+	/// Value added to the texture's values, from `-mm`
+	pub base: f32,
+	/// Range the texture's values are scaled to, from `-mm`
+	pub gain: f32,
+	/// Offset of the texture's origin, from `-o`
+	pub offset: [f32; 3],
+	/// Scale of the texture, from `-s`
+	pub scale: [f32; 3],
+	/// Turbulence added to texture coordinates, from `-t`
+	pub turbulence: [f32; 3],
+	/// Resolution of the texture created from the image, from `-texres`
+	pub resolution: Option<u32>,
+	/// How a reflection map is projected, from `-type`
+	pub reflection_type: Option<ReflectionType>,
+}
+
+impl TextureMap {
+	/// A texture with every option at its default
+	pub fn new(file: String) -> TextureMap {
+		TextureMap {
+			file,
+			blend_u: true,
+			blend_v: true,
+			bump_multiplier: 1.0,
+			boost: None,
+			color_correction: false,
+			clamp: false,
+			channel: None,
+			base: 0.0,
+			gain: 1.0,
+			offset: [ 0.0, 0.0, 0.0 ],
+			scale: [ 1.0, 1.0, 1.0 ],
+			turbulence: [ 0.0, 0.0, 0.0 ],
+			resolution: None,
+			reflection_type: None,
+		}
+	}
+
+	fn apply(&mut self, option: TextureOption) {
+		match option {
+			TextureOption::BlendU(on) => self.blend_u = on,
+			TextureOption::BlendV(on) => self.blend_v = on,
+			TextureOption::BumpMultiplier(multiplier) => self.bump_multiplier = multiplier,
+			TextureOption::Boost(boost) => self.boost = Some(boost),
+			TextureOption::ColorCorrection(on) => self.color_correction = on,
+			TextureOption::Clamp(on) => self.clamp = on,
+			TextureOption::Channel(channel) => self.channel = Some(channel),
+			TextureOption::Range(base, gain) => {
+				self.base = base;
+				self.gain = gain;
+			},
+			TextureOption::Offset((u, v, w)) => self.offset = [ u, v.unwrap_or(0.0), w.unwrap_or(0.0) ],
+			TextureOption::Scale((u, v, w)) => self.scale = [ u, v.unwrap_or(1.0), w.unwrap_or(1.0) ],
+			TextureOption::Turbulence((u, v, w)) => self.turbulence = [ u, v.unwrap_or(0.0), w.unwrap_or(0.0) ],
+			TextureOption::Resolution(resolution) => self.resolution = Some(resolution),
+			TextureOption::Type(reflection_type) => self.reflection_type = Some(reflection_type),
+		}
+	}
+}
+
+/// Channel of an image used for a scalar texture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureChannel {
+	Red,
+	Green,
+	Blue,
+	Matte,
+	Luminance,
+	Depth,
+}
+
+/// How a reflection map is projected onto the scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReflectionType {
+	Sphere,
+	CubeTop,
+	CubeBottom,
+	CubeFront,
+	CubeBack,
+	CubeLeft,
+	CubeRight,
+}
+
+/// An option of a texture map statement as written. Vectors have optional second and third
+/// components.
+#[derive(Debug)]
+enum TextureOption {
+	BlendU(bool),
+	BlendV(bool),
+	BumpMultiplier(f32),
+	Boost(f32),
+	ColorCorrection(bool),
+	Clamp(bool),
+	Channel(TextureChannel),
+	Range(f32, f32),
+	Offset((f32, Option<f32>, Option<f32>)),
+	Scale((f32, Option<f32>, Option<f32>)),
+	Turbulence((f32, Option<f32>, Option<f32>)),
+	Resolution(u32),
+	Type(ReflectionType),
+}
+
+/// The material property a texture map statement sets
+#[derive(Debug)]
+enum MapKind {
+	Ambient,
+	Diffuse,
+	Specular,
+	SpecularExponent,
+	Dissolve,
+	Bump,
+	Displacement,
+	Decal,
+	Reflection,
+	Roughness,
+	Metallic,
+	Sheen,
+	Normal,
+	RoughnessMetallicOcclusion,
+	OcclusionRoughnessMetallic,
+}
+
+/// A scalar property from the PBR extension
+#[derive(Debug)]
+enum PbrProperty {
+	Roughness,
+	Metallic,
+	Sheen,
+	ClearcoatThickness,
+	ClearcoatRoughness,
+	Anisotropy,
+	AnisotropyRotation,
 }
 
-named!(parse_vector3<&[u8], [f32; 3]>,
+// A colour as `r g b`, or a single value used for all three
+named!(parse_color<&[u8], [f32; 3]>,
 	do_parse!(
-		x: parse_float >>
-		space >>
-		y: parse_float >>
+		r: parse_float >>
+		gb: opt!(pair!(preceded!(space, parse_float), preceded!(space, parse_float))) >>
+		line_ending >>
+		({
+			let (g, b) = gb.unwrap_or((r, r));
+
+			[ r, g, b ]
+		})
+	)
+);
+
+// A colour as `r g b`, `xyz x y z` or `spectral file factor`, where the factor defaults to 1. The
+// file is an `.rfl` spectral curve, read by `spectral`.
+named!(color<&[u8], Color>,
+	alt!(
+		do_parse!(
+			tag!("spectral") >>
+			space >>
+			file: is_not!(" \t\r\n") >>
+			factor: opt!(preceded!(space, parse_float)) >>
+			line_ending >>
+			(Color::Spectral(String::from_utf8_lossy(file).into_owned(), factor.unwrap_or(1.0)))
+		) |
+		preceded!(terminated!(tag!("xyz"), space), parse_color) => { Color::Xyz } |
+		parse_color => { Color::Rgb }
+	)
+);
+
+// `d factor` or `d -halo factor`
+named!(dissolve<&[u8], (bool, f32)>,
+	do_parse!(
+		tag!("d") >>
 		space >>
-		z: parse_float >>
+		halo: opt!(terminated!(tag!("-halo"), space)) >>
+		factor: parse_float >>
 		line_ending >>
-		([ x, y, z ])
+		(halo.is_some(), factor)
+	)
+);
+
+// A number in a texture option. It must be followed by a space and more of the statement, so the
+// start of a file name like `1.png` isn't taken for one and a number at the end of the line is
+// left to be the file name.
+named!(texture_value<&[u8], f32>,
+	terminated!(parse_float, peek!(preceded!(space, none_of!("\r\n"))))
+);
+
+// `u [v [w]]` of `-o`, `-s` and `-t`. As many values are taken as possible, only leaving one for
+// the file name when the statement would otherwise have none, so `-o 1 2` is an offset of 1 for
+// the file `2`.
+named!(texture_vector<&[u8], (f32, Option<f32>, Option<f32>)>,
+	tuple!(
+		texture_value,
+		opt!(preceded!(space, texture_value)),
+		opt!(preceded!(space, texture_value))
 	)
 );
 
-named!(negative, tag!("-"));
+named!(on_off<&[u8], bool>,
+	alt!(
+		tag!("on") => { |_| true } |
+		tag!("off") => { |_| false }
+	)
+);
 
-named!(decimal, complete!(chain!(
-    tag!(".")  ~
-    val: digit ,
-    || val
-)));
+named!(texture_channel<&[u8], TextureChannel>,
+	alt!(
+		tag!("r") => { |_| TextureChannel::Red } |
+		tag!("g") => { |_| TextureChannel::Green } |
+		tag!("b") => { |_| TextureChannel::Blue } |
+		tag!("m") => { |_| TextureChannel::Matte } |
+		tag!("l") => { |_| TextureChannel::Luminance } |
+		tag!("z") => { |_| TextureChannel::Depth }
+	)
+);
+
+named!(reflection_type<&[u8], ReflectionType>,
+	alt!(
+		tag!("sphere") => { |_| ReflectionType::Sphere } |
+		tag!("cube_top") => { |_| ReflectionType::CubeTop } |
+		tag!("cube_bottom") => { |_| ReflectionType::CubeBottom } |
+		tag!("cube_front") => { |_| ReflectionType::CubeFront } |
+		tag!("cube_back") => { |_| ReflectionType::CubeBack } |
+		tag!("cube_left") => { |_| ReflectionType::CubeLeft } |
+		tag!("cube_right") => { |_| ReflectionType::CubeRight }
+	)
+);
+
+named!(texture_option<&[u8], TextureOption>,
+	alt!(
+		do_parse!(tag!("-blendu") >> space >> on: on_off >> (TextureOption::BlendU(on))) |
+		do_parse!(tag!("-blendv") >> space >> on: on_off >> (TextureOption::BlendV(on))) |
+		do_parse!(tag!("-bm") >> space >> multiplier: texture_value >> (TextureOption::BumpMultiplier(multiplier))) |
+		do_parse!(tag!("-boost") >> space >> boost: texture_value >> (TextureOption::Boost(boost))) |
+		do_parse!(tag!("-cc") >> space >> on: on_off >> (TextureOption::ColorCorrection(on))) |
+		do_parse!(tag!("-clamp") >> space >> on: on_off >> (TextureOption::Clamp(on))) |
+		do_parse!(tag!("-imfchan") >> space >> channel: texture_channel >> (TextureOption::Channel(channel))) |
+		do_parse!(tag!("-mm") >> space >> base: texture_value >> space >> gain: texture_value >> (TextureOption::Range(base, gain))) |
+		do_parse!(tag!("-o") >> space >> offset: texture_vector >> (TextureOption::Offset(offset))) |
+		do_parse!(tag!("-s") >> space >> scale: texture_vector >> (TextureOption::Scale(scale))) |
+		do_parse!(tag!("-texres") >> space >> resolution: parse_unsigned >> (TextureOption::Resolution(resolution))) |
+		do_parse!(tag!("-type") >> space >> reflection_type: reflection_type >> (TextureOption::Type(reflection_type))) |
+		do_parse!(tag!("-t") >> space >> turbulence: texture_vector >> (TextureOption::Turbulence(turbulence)))
+	)
+);
 
-named!(parse_float<f32>,
+// The options and file name of a texture map statement. The file name is the rest of the
+// statement, so may contain spaces, but may not start with `-` so unknown options are reported.
+named!(texture_map<&[u8], TextureStatement>,
 	do_parse!(
-		sign: opt!(negative) >>
-		bef: digit >>
-		aft: opt!(decimal) >>
+		options: many0!(terminated!(texture_option, space)) >>
+		not!(tag!("-")) >>
+		file: is_not!("\r\n") >>
+		line_ending >>
 		({
-			let a = match sign {
-				Some(sign) => str::from_utf8(sign).unwrap(),
-				None => ""
-			};
+			// A vector with values to spare only stops short of a number if it is the last thing
+			// in the statement, so that number may have been meant for the vector
+			let file_from = match options.last() {
+				Some(&TextureOption::Offset((_, _, None))) => Some("-o"),
+				Some(&TextureOption::Scale((_, _, None))) => Some("-s"),
+				Some(&TextureOption::Turbulence((_, _, None))) => Some("-t"),
+				_ => None,
+			}.filter(|_| matches!(parse_float(file), IResult::Done(rest, _) if rest.is_empty()));
 
-			let b = str::from_utf8(bef).unwrap();
+			let mut map = TextureMap::new(String::from_utf8_lossy(file).into_owned());
 
-			let c = match aft {
-				Some(aft) => str::from_utf8(aft).unwrap(),
-				None => ""
-			};
+			for option in options {
+				map.apply(option);
+			}
 
-			format!("{}{}.{}", a, b, c).parse::<f32>().unwrap()
+			TextureStatement { map, file_from }
 		})
 	)
 );
 
+/// A parsed texture map statement
+#[derive(Debug)]
+struct TextureStatement {
+	map: TextureMap,
+	/// The vector option the file name was left over from, when it is a number
+	file_from: Option<&'static str>,
+}
+
 named!(material_start<&[u8], String>,
 	do_parse!(
 		tag!("newmtl") >>
@@ -67,103 +523,520 @@ named!(material_start<&[u8], String>,
 	)
 );
 
-// named!(material<&[u8], WavefrontMaterial>,
-// 	do_parse!(
-// 		name: material_start >>
-// 		tag!("Ns") >> space >> specular_exponent: parse_float >> line_ending >>
-// 		tag!("Ka") >> space >> ambient: parse_vector3 >>
-// 		tag!("Kd") >> space >> diffuse: parse_vector3 >>
-// 		tag!("Ks") >> space >> specular: parse_vector3 >>
-
-// 		tag!("Ke") >> not_line_ending >> line_ending >>
-// 		tag!("Ni") >> not_line_ending >> line_ending >>
-// 		tag!("d") >> not_line_ending >> line_ending >>
-
-// 		tag!("illum") >> space >> illum: parse_u32 >>
-// 		(WavefrontMaterial {
-// 			name: name,
-// 			specular_exponent: specular_exponent,
-// 			ambient: ambient,
-// 			diffuse: diffuse,
-// 			specular: specular,
-// 			illum: illum,
-// 		})
-// 	)
-// );
-
-// named!(mtl_file<&[u8], Vec<WavefrontMaterial>>,
-// 	do_parse!(
-// 		many0!(comment) >>
-// 		opt!(multispace) >>
-// 		materials: many1!(material) >>
-// 		(materials)
-// 	)
-// );
+/// A colour as written in a statement, before conversion to RGB
+#[derive(Debug)]
+enum Color {
+	Rgb([f32; 3]),
+	Xyz([f32; 3]),
+	Spectral(String, f32),
+}
 
 #[derive(Debug)]
 enum FileEntity {
 	Name(String),
-	Ambient([f32; 3]),
-	Diffuse([f32; 3]),
-	Specular([f32; 3]),
+	Ambient(Color),
+	Diffuse(Color),
+	Specular(Color),
 	Exponent(f32),
+	Emissive(Color),
+	OpticalDensity(f32),
+	Dissolve(bool, f32),
+	Transparency(f32),
+	TransmissionFilter(Color),
+	IlluminationModel(u32),
+	Map(MapKind, TextureStatement),
+	Pbr(PbrProperty, f32),
 
+	Unsupported,
+	Unknown,
 	Ignore
 }
 
-named!(entity<&[u8], FileEntity>, alt!(
-	material_start => { |name| FileEntity::Name(name) } |
-	preceded!(tag!("Ns "), parse_float) => { |exp| FileEntity::Exponent(exp) } |
-	preceded!(tag!("Ka "), parse_vector3) => { |a| FileEntity::Ambient(a) } |
-	preceded!(tag!("Kd "), parse_vector3) => { |d| FileEntity::Diffuse(d) } |
-	preceded!(tag!("Ks "), parse_vector3) => { |s| FileEntity::Specular(s) } |
-
-	take_until_and_consume!("\n") => { |_| FileEntity::Ignore }
-));
-
-named!(file<&[u8], Vec<FileEntity>>, many1!(entity));
-
-pub fn parse(input: &[u8]) -> Result<WavefrontMaterials, String> {
-	match file(input) {
-		IResult::Done(_, lines) => {
-			let mut materials = Vec::new();
-
-			let mut map = HashMap::new();
-
-			for line in lines.into_iter() {
-				match line {
-					FileEntity::Name(ref name) => {
-						materials.push(WavefrontMaterial {
-							name: (*name).clone(),
-							ambient: [ 0.0, 0.0, 0.0 ],
-							diffuse: [ 0.0, 0.0, 0.0 ],
-							specular: [ 0.0, 0.0, 0.0 ],
-							specular_exponent: 0.0,
-						})
-					},
-					FileEntity::Ambient(ref a) => { materials.last_mut().unwrap().ambient = *a },
-					FileEntity::Diffuse(ref d) => { materials.last_mut().unwrap().diffuse = *d },
-					FileEntity::Specular(ref s) => { materials.last_mut().unwrap().specular = *s },
-					FileEntity::Exponent(ref exp) => { materials.last_mut().unwrap().specular_exponent = *exp },
-					FileEntity::Ignore => {}
-				}
-			}
+/// Statements from the MTL specification that are recognised but not supported
+const UNSUPPORTED: &[&[u8]] = &[
+	b"sharpness", b"map_aat",
+];
 
-			for material in materials.iter() {
-				map.insert(material.name.clone(), material.clone());
-			}
+/// Parse a statement with the parser for its keyword, so a malformed statement is reported rather
+/// than skipped. Blank lines and comments are ignored.
+fn entity(line: &[u8]) -> IResult<&[u8], FileEntity> {
+	let (keyword, input) = lines::keyword(line);
+
+	match keyword {
+		b"newmtl" => map!(input, material_start, FileEntity::Name),
+		b"Ns" => map!(input, preceded!(tag!("Ns"), delimited!(space, parse_float, line_ending)), FileEntity::Exponent),
+		b"Ka" => map!(input, preceded!(tag!("Ka"), preceded!(space, color)), FileEntity::Ambient),
+		b"Kd" => map!(input, preceded!(tag!("Kd"), preceded!(space, color)), FileEntity::Diffuse),
+		b"Ks" => map!(input, preceded!(tag!("Ks"), preceded!(space, color)), FileEntity::Specular),
+		b"Ke" => map!(input, preceded!(tag!("Ke"), preceded!(space, color)), FileEntity::Emissive),
+		b"Ni" => map!(input, preceded!(tag!("Ni"), delimited!(space, parse_float, line_ending)), FileEntity::OpticalDensity),
+		b"d" => map!(input, dissolve, |(halo, factor)| FileEntity::Dissolve(halo, factor)),
+		b"Tr" => map!(input, preceded!(tag!("Tr"), delimited!(space, parse_float, line_ending)), FileEntity::Transparency),
+		b"Tf" => map!(input, preceded!(tag!("Tf"), preceded!(space, color)), FileEntity::TransmissionFilter),
+		b"illum" => map!(input, preceded!(tag!("illum"), delimited!(space, parse_unsigned, line_ending)), FileEntity::IlluminationModel),
+		b"map_Ka" => map!(input, preceded!(tag!("map_Ka"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Ambient, map)),
+		b"map_Kd" => map!(input, preceded!(tag!("map_Kd"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Diffuse, map)),
+		b"map_Ks" => map!(input, preceded!(tag!("map_Ks"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Specular, map)),
+		b"map_Ns" => map!(input, preceded!(tag!("map_Ns"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::SpecularExponent, map)),
+		b"map_d" => map!(input, preceded!(tag!("map_d"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Dissolve, map)),
+		// Many exporters write `map_Bump`
+		b"bump" | b"map_bump" | b"map_Bump" => map!(input, preceded!(tag!(keyword), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Bump, map)),
+		b"disp" => map!(input, preceded!(tag!("disp"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Displacement, map)),
+		b"decal" => map!(input, preceded!(tag!("decal"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Decal, map)),
+		b"refl" => map!(input, preceded!(tag!("refl"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Reflection, map)),
+		b"Pr" => map!(input, preceded!(tag!("Pr"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::Roughness, value)),
+		b"Pm" => map!(input, preceded!(tag!("Pm"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::Metallic, value)),
+		b"Ps" => map!(input, preceded!(tag!("Ps"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::Sheen, value)),
+		b"Pc" => map!(input, preceded!(tag!("Pc"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::ClearcoatThickness, value)),
+		b"Pcr" => map!(input, preceded!(tag!("Pcr"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::ClearcoatRoughness, value)),
+		b"aniso" => map!(input, preceded!(tag!("aniso"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::Anisotropy, value)),
+		b"anisor" => map!(input, preceded!(tag!("anisor"), delimited!(space, parse_float, line_ending)), |value| FileEntity::Pbr(PbrProperty::AnisotropyRotation, value)),
+		b"map_Pr" => map!(input, preceded!(tag!("map_Pr"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Roughness, map)),
+		b"map_Pm" => map!(input, preceded!(tag!("map_Pm"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Metallic, map)),
+		b"map_Ps" => map!(input, preceded!(tag!("map_Ps"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Sheen, map)),
+		b"norm" => map!(input, preceded!(tag!("norm"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::Normal, map)),
+		b"map_RMA" => map!(input, preceded!(tag!("map_RMA"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::RoughnessMetallicOcclusion, map)),
+		b"map_ORM" => map!(input, preceded!(tag!("map_ORM"), preceded!(space, texture_map)), |map| FileEntity::Map(MapKind::OcclusionRoughnessMetallic, map)),
+		b"" => IResult::Done(&line[line.len()..], FileEntity::Ignore),
+		_ if UNSUPPORTED.contains(&keyword) => IResult::Done(&line[line.len()..], FileEntity::Unsupported),
+		_ => IResult::Done(&line[line.len()..], FileEntity::Unknown),
+	}
+}
+
+/// Options for parsing MTL files
+#[derive(Debug, Clone, Default)]
+pub struct MtlOptions {
+	pub mode: ParseMode,
+	/// Light `spectral` colours are viewed under when converting them to RGB
+	pub illuminant: Illuminant,
+	/// Directory the spectral curve files named by `spectral` colours are found in. Paths are
+	/// relative to the working directory when this is `None`.
+	pub directory: Option<PathBuf>,
+}
+
+/// Parse the contents of an MTL file into a map of materials keyed by name, stopping at the first
+/// malformed or unknown statement. Use `parse_with_options` with `ParseMode::Lenient` to skip
+/// them instead and see what was skipped.
+pub fn parse(input: &[u8]) -> Result<WavefrontMaterials, WavefrontError> {
+	parse_with_options(input, &MtlOptions::default()).map(|(materials, _)| materials)
+}
+
+/// Parse the contents of an MTL file, also returning the statements that were ignored or skipped
+pub fn parse_with_options(input: &[u8], options: &MtlOptions) -> Result<(WavefrontMaterials, Vec<Warning>), WavefrontError> {
+	let mut lines = Lines::new(input);
+
+	let (materials, warnings) = parse_lines(&mut lines, options).map_err(|error| lines.locate(error))?;
+
+	Ok((materials, warnings.into_iter().map(|warning| lines.locate_warning(warning)).collect()))
+}
+
+fn parse_lines(lines: &mut Lines, options: &MtlOptions) -> Result<(WavefrontMaterials, Vec<Warning>), WavefrontError> {
+	let mut materials: Vec<WavefrontMaterial> = Vec::new();
+	let mut warnings = Vec::new();
+
+	let mut map = HashMap::new();
+
+	for (line_number, line) in lines.by_ref() {
+		let line = &line[..];
 
-			Ok(map)
+		match statement(&mut materials, &mut warnings, options, line_number, line) {
+			Ok(()) => (),
+			Err(error) => match options.mode {
+				ParseMode::Strict => return Err(error),
+				ParseMode::Lenient => warnings.push(error.into_warning()?),
+			},
+		}
+	}
+
+	for material in materials.iter() {
+		map.insert(material.name.clone(), material.clone());
+	}
+
+	Ok((map, warnings))
+}
+
+/// Parse a statement and apply it to the material it belongs to
+fn statement(materials: &mut Vec<WavefrontMaterial>, warnings: &mut Vec<Warning>, options: &MtlOptions, line_number: usize, line: &[u8]) -> Result<(), WavefrontError> {
+	let entity = parse_line(entity, line_number, line)?;
+
+	// Reported in both modes, as the statement is valid but probably not what was meant
+	if let FileEntity::Map(_, TextureStatement { ref map, file_from: Some(option) }) = entity {
+		let file_token = line.split(|c| c.is_ascii_whitespace()).filter(|token| !token.is_empty()).count() - 1;
+
+		warnings.push(Warning {
+			location: Location::new(line_number, token_column(line, file_token), line),
+			message: format!("`{}` was taken as the file name rather than a value of `{}`", map.file, option),
+		});
+	}
+
+	match entity {
+		FileEntity::Name(name) => materials.push(WavefrontMaterial::new(name)),
+		FileEntity::Unsupported => warnings.push(Warning {
+			location: Location::new(line_number, token_column(line, 0), line),
+			message: format!("`{}` statements are not supported and were ignored", keyword(line)),
+		}),
+		FileEntity::Unknown => return Err(WavefrontError::UnknownStatement {
+			location: Location::new(line_number, token_column(line, 0), line),
+			message: format!("unknown statement `{}`", keyword(line)),
+		}),
+		FileEntity::Ignore => (),
+		property => {
+			let material = materials.last_mut().ok_or_else(|| WavefrontError::UnexpectedStatement {
+				location: Location::new(line_number, token_column(line, 0), line),
+				message: String::from("Material property before any `newmtl` statement"),
+			})?;
+
+			match property {
+				FileEntity::Ambient(a) => material.ambient = to_rgb(a, options, line_number, line)?,
+				FileEntity::Diffuse(d) => material.diffuse = to_rgb(d, options, line_number, line)?,
+				FileEntity::Specular(s) => material.specular = to_rgb(s, options, line_number, line)?,
+				FileEntity::Exponent(exp) => material.specular_exponent = exp,
+				FileEntity::Emissive(e) => material.emissive = to_rgb(e, options, line_number, line)?,
+				FileEntity::OpticalDensity(density) => material.optical_density = density,
+				FileEntity::Dissolve(halo, factor) => {
+					material.dissolve = factor;
+					material.halo = halo;
+				},
+				FileEntity::Transparency(transparency) => {
+					material.dissolve = 1.0 - transparency;
+					material.halo = false;
+				},
+				FileEntity::TransmissionFilter(filter) => material.transmission_filter = to_rgb(filter, options, line_number, line)?,
+				FileEntity::IlluminationModel(number) => {
+					material.illumination_model = IlluminationModel::from_number(number).ok_or_else(|| WavefrontError::InvalidElement {
+						location: Location::new(line_number, token_column(line, 1), line),
+						message: format!("Illumination model {} is not one of 0 to 10", number),
+					})?;
+				},
+				FileEntity::Map(MapKind::Ambient, texture) => material.ambient_map = Some(texture.map),
+				FileEntity::Map(MapKind::Diffuse, texture) => material.diffuse_map = Some(texture.map),
+				FileEntity::Map(MapKind::Specular, texture) => material.specular_map = Some(texture.map),
+				FileEntity::Map(MapKind::SpecularExponent, texture) => material.specular_exponent_map = Some(texture.map),
+				FileEntity::Map(MapKind::Dissolve, texture) => material.dissolve_map = Some(texture.map),
+				FileEntity::Map(MapKind::Bump, texture) => material.bump_map = Some(texture.map),
+				FileEntity::Map(MapKind::Displacement, texture) => material.displacement_map = Some(texture.map),
+				FileEntity::Map(MapKind::Decal, texture) => material.decal_map = Some(texture.map),
+				FileEntity::Map(MapKind::Reflection, texture) => material.reflection_maps.push(texture.map),
+				FileEntity::Map(kind, TextureStatement { map, .. }) => {
+					let pbr = material.pbr.get_or_insert_with(PbrMaterial::default);
+
+					match kind {
+						MapKind::Roughness => pbr.roughness_map = Some(map),
+						MapKind::Metallic => pbr.metallic_map = Some(map),
+						MapKind::Sheen => pbr.sheen_map = Some(map),
+						MapKind::Normal => pbr.normal_map = Some(map),
+						MapKind::RoughnessMetallicOcclusion => pbr.rma_map = Some(map),
+						MapKind::OcclusionRoughnessMetallic => pbr.orm_map = Some(map),
+						_ => (),
+					}
+				},
+				FileEntity::Pbr(property, value) => {
+					let pbr = material.pbr.get_or_insert_with(PbrMaterial::default);
+
+					match property {
+						PbrProperty::Roughness => pbr.roughness = Some(value),
+						PbrProperty::Metallic => pbr.metallic = Some(value),
+						PbrProperty::Sheen => pbr.sheen = Some(value),
+						PbrProperty::ClearcoatThickness => pbr.clearcoat_thickness = Some(value),
+						PbrProperty::ClearcoatRoughness => pbr.clearcoat_roughness = Some(value),
+						PbrProperty::Anisotropy => pbr.anisotropy = Some(value),
+						PbrProperty::AnisotropyRotation => pbr.anisotropy_rotation = Some(value),
+					}
+				},
+				_ => (),
+			}
 		},
-		IResult::Incomplete(need) => {
-			Err(format!("Incomplete, {:?}", need))
+	}
+
+	Ok(())
+}
+
+/// Convert a colour to RGB, loading the spectral curve it names if there is one
+fn to_rgb(color: Color, options: &MtlOptions, line_number: usize, line: &[u8]) -> Result<[f32; 3], WavefrontError> {
+	match color {
+		Color::Rgb(rgb) => Ok(rgb),
+		Color::Xyz(xyz) => Ok(spectral::xyz_to_rgb(xyz)),
+		Color::Spectral(file, factor) => {
+			let path = match options.directory {
+				Some(ref directory) => directory.join(&file),
+				None => PathBuf::from(&file),
+			};
+
+			let curve = spectral::load_curve(&path).map_err(|message| WavefrontError::InvalidElement {
+				location: Location::new(line_number, token_column(line, 2), line),
+				message,
+			})?;
+
+			let [ r, g, b ] = spectral::spectrum_to_rgb(&curve, &options.illuminant);
+
+			Ok([ r * factor, g * factor, b * factor ])
 		},
-		IResult::Error(err) => {
-			match err {
-				nom::Err::NodePosition(kind, position, _) => Err(format!("NodePosition {:?} {:?}", kind, str::from_utf8(position).unwrap())),
-				nom::Err::Position(kind, position) => Err(format!("Position {:?} {:?}", kind, str::from_utf8(position).unwrap())),
-				_ => Err(format!("Some error: {:?}", err))
-			}
+	}
+}
+
+/// The keyword a statement starts with, for messages
+fn keyword(line: &[u8]) -> String {
+	String::from_utf8_lossy(lines::keyword(line).0).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::testing::assert_close;
+	use std::fs;
+
+	/// The diffuse map of a material given by a `map_Kd` statement
+	fn diffuse_map(statement: &str) -> TextureMap {
+		let input = format!("newmtl test\n{}\n", statement);
+		let options = MtlOptions { mode: ParseMode::Strict, ..MtlOptions::default() };
+		let (materials, _) = parse_with_options(input.as_bytes(), &options).expect(statement);
+
+		materials["test"].diffuse_map.clone().expect(statement)
+	}
+
+	/// The material defined by `statements`
+	fn material(statements: &str) -> WavefrontMaterial {
+		let input = format!("newmtl test\n{}", statements);
+		let (materials, _) = parse_with_options(input.as_bytes(), &MtlOptions::default()).expect(statements);
+
+		materials["test"].clone()
+	}
+
+	#[test]
+	fn material_defaults() {
+		for material in &[ WavefrontMaterial::new(String::from("test")), material("") ] {
+			assert_eq!(material.name, "test");
+			assert_eq!((material.ambient, material.diffuse, material.specular, material.emissive), ([ 0.0; 3 ], [ 0.0; 3 ], [ 0.0; 3 ], [ 0.0; 3 ]));
+			assert_eq!(material.specular_exponent, 0.0);
+			assert_eq!(material.optical_density, 1.0);
+			assert_eq!((material.dissolve, material.halo), (1.0, false));
+			assert_eq!(material.transmission_filter, [ 1.0, 1.0, 1.0 ]);
+			assert_eq!(material.illumination_model, IlluminationModel::Highlight);
+			assert!(material.diffuse_map.is_none() && material.bump_map.is_none() && material.reflection_maps.is_empty());
+			assert!(material.pbr.is_none());
 		}
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn material_scalars() {
+		let material = material("Ke 0.1 0.2 0.3\nNi 1.45\nNs 10\nd 0.25\nTf 0.5 0.6 0.7\n");
+
+		assert_eq!(material.emissive, [ 0.1, 0.2, 0.3 ]);
+		assert_eq!(material.optical_density, 1.45);
+		assert_eq!(material.specular_exponent, 10.0);
+		assert_eq!((material.dissolve, material.halo), (0.25, false));
+		assert_eq!(material.transmission_filter, [ 0.5, 0.6, 0.7 ]);
+
+		let material = self::material("d -halo 0.5\n");
+
+		assert_eq!((material.dissolve, material.halo), (0.5, true));
+	}
+
+	#[test]
+	fn transparency_is_inverse_dissolve() {
+		let material = self::material("Tr 0.25\n");
+
+		assert_eq!((material.dissolve, material.halo), (0.75, false));
+
+		// Whichever of the two comes last wins, and `Tr` has no halo form
+		let replaced = self::material("d -halo 0.5\nTr 0.25\n");
+
+		assert_eq!((replaced.dissolve, replaced.halo), (0.75, false));
+		assert_eq!(self::material("Tr 0.25\nd 0.5\n").dissolve, 0.5);
+		assert_eq!(self::material("Tr 0\n").dissolve, 1.0);
+		assert_eq!(self::material("Tr 1\n").dissolve, 0.0);
+	}
+
+	#[test]
+	fn color_forms() {
+		let directory = std::env::temp_dir().join(format!("obj-rs-rfl-{}", std::process::id()));
+
+		fs::create_dir_all(&directory).unwrap();
+		fs::write(directory.join("grey.rfl"), "# flat\nsp 380 780\n0.5 0.5 0.5\n").unwrap();
+		fs::write(directory.join("pairs.rfl"), "380 0.25\n780 0.25\n").unwrap();
+
+		let options = MtlOptions { directory: Some(directory.clone()), ..MtlOptions::default() };
+		let colors = |keyword: &str| {
+			let input = format!(
+				"newmtl single\n{0} 0.5\nnewmtl xyz\n{0} xyz 0.95047 1 1.08883\nnewmtl spectral\n{0} spectral grey.rfl\nnewmtl factor\n{0} spectral pairs.rfl 2\n",
+				keyword
+			);
+			let (materials, _) = parse_with_options(input.as_bytes(), &options).expect(keyword);
+			let missing = parse_with_options(format!("newmtl test\n{} spectral missing.rfl\n", keyword).as_bytes(), &options);
+
+			(materials, missing)
+		};
+		let field = |keyword: &str, material: &WavefrontMaterial| match keyword {
+			"Ka" => material.ambient,
+			"Kd" => material.diffuse,
+			"Ks" => material.specular,
+			"Ke" => material.emissive,
+			_ => material.transmission_filter,
+		};
+
+		let results: Vec<_> = [ "Ka", "Kd", "Ks", "Ke", "Tf" ].iter().map(|&keyword| (keyword, colors(keyword))).collect();
+
+		fs::remove_dir_all(&directory).unwrap();
+
+		for (keyword, (materials, missing)) in results {
+			let field = |name: &str| field(keyword, &materials[name]);
+
+			assert_eq!(field("single"), [ 0.5; 3 ], "{}", keyword);
+			assert_close(field("xyz"), [ 1.0; 3 ], 1e-3);
+			assert_close(field("spectral"), [ 0.5; 3 ], 1e-3);
+			assert_close(field("factor"), [ 0.5; 3 ], 1e-3);
+
+			let error = missing.unwrap_err();
+
+			assert!(matches!(error, WavefrontError::InvalidElement { .. }), "{}: {:?}", keyword, error);
+			assert_eq!(error.location().map(|location| (location.line, location.column)), Some((2, 13)));
+		}
+	}
+
+	#[test]
+	fn pbr_properties() {
+		assert_eq!(material("Kd 1 1 1\nNs 10\nmap_Kd file.png\n").pbr, None);
+
+		let pbr = material("Pr 0.1\nPm 0.2\nPs 0.3\nPc 0.4\nPcr 0.5\naniso 0.6\nanisor 0.7\n").pbr.unwrap();
+
+		assert_eq!(
+			(pbr.roughness, pbr.metallic, pbr.sheen, pbr.clearcoat_thickness, pbr.clearcoat_roughness, pbr.anisotropy, pbr.anisotropy_rotation),
+			(Some(0.1), Some(0.2), Some(0.3), Some(0.4), Some(0.5), Some(0.6), Some(0.7))
+		);
+		assert_eq!((pbr.roughness_map, pbr.normal_map), (None, None));
+
+		let pbr = material("norm normal.png\nmap_Pr r.png\nmap_Pm m.png\nmap_Ps s.png\nmap_RMA rma.png\nmap_ORM orm.png\n").pbr.unwrap();
+		let file = |map: Option<TextureMap>| map.map(|map| map.file);
+
+		assert_eq!(pbr.roughness, None);
+		assert_eq!(
+			[ file(pbr.normal_map), file(pbr.roughness_map), file(pbr.metallic_map), file(pbr.sheen_map), file(pbr.rma_map), file(pbr.orm_map) ],
+			[ "normal.png", "r.png", "m.png", "s.png", "rma.png", "orm.png" ].map(|file| Some(String::from(file)))
+		);
+
+		// A single statement is enough
+		assert_eq!(material("Pm 1\n").pbr, Some(PbrMaterial { metallic: Some(1.0), ..PbrMaterial::default() }));
+	}
+
+	#[test]
+	fn texture_vectors() {
+		let map = diffuse_map("map_Kd -o 1 2 3 file.png");
+
+		assert_eq!((map.offset, map.file.as_str()), ([ 1.0, 2.0, 3.0 ], "file.png"));
+
+		let map = diffuse_map("map_Kd -o 1 file.png");
+
+		assert_eq!((map.offset, map.file.as_str()), ([ 1.0, 0.0, 0.0 ], "file.png"));
+
+		let map = diffuse_map("map_Kd -s 2 3 file.png");
+
+		assert_eq!((map.scale, map.file.as_str()), ([ 2.0, 3.0, 1.0 ], "file.png"));
+
+		let map = diffuse_map("map_Kd -t 0.1 0.2 0.3 -o 1 file.png");
+
+		assert_eq!((map.turbulence, map.offset), ([ 0.1, 0.2, 0.3 ], [ 1.0, 0.0, 0.0 ]));
+	}
+
+	#[test]
+	fn texture_vector_leaves_file_name() {
+		// Values are only left for the file name when nothing else would be
+		let map = diffuse_map("map_Kd -o 1 2");
+
+		assert_eq!((map.offset, map.file.as_str()), ([ 1.0, 0.0, 0.0 ], "2"));
+
+		let map = diffuse_map("map_Kd -o 1 2 3 4");
+
+		assert_eq!((map.offset, map.file.as_str()), ([ 1.0, 2.0, 3.0 ], "4"));
+
+		let map = diffuse_map("map_Kd -s 1 2 3.png");
+
+		assert_eq!((map.scale, map.file.as_str()), ([ 1.0, 2.0, 1.0 ], "3.png"));
+
+		let map = diffuse_map("map_Kd -bm 2 2");
+
+		assert_eq!((map.bump_multiplier, map.file.as_str()), (2.0, "2"));
+
+		// Even strict parsing warns, as the statement is valid but probably not what was meant
+		let warnings = |statement: &str| {
+			let input = format!("newmtl test\n{}\n", statement);
+			let (_, warnings) = parse_with_options(input.as_bytes(), &MtlOptions::default()).expect(statement);
+
+			warnings.iter().map(|warning| (warning.location.line, warning.location.column, warning.message.clone())).collect::<Vec<_>>()
+		};
+
+		assert_eq!(warnings("map_Kd -o 1 2 3"), [ (2, 15, String::from("`3` was taken as the file name rather than a value of `-o`")) ]);
+		assert_eq!(warnings("map_Kd -t 1 2"), [ (2, 13, String::from("`2` was taken as the file name rather than a value of `-t`")) ]);
+		assert!(warnings("map_Kd -o 1 2 3 4").is_empty());
+		assert!(warnings("map_Kd -s 1 2 3.png").is_empty());
+		assert!(warnings("map_Kd -bm 2 2").is_empty());
+	}
+
+	#[test]
+	fn texture_options() {
+		let map = diffuse_map("map_Kd -bm 0.5 file.png");
+
+		assert_eq!((map.bump_multiplier, map.file.as_str()), (0.5, "file.png"));
+
+		let map = diffuse_map("map_Kd -clamp on file.png");
+
+		assert!(map.clamp);
+		assert!(!diffuse_map("map_Kd -clamp off -blendu off file.png").clamp);
+		assert_eq!(diffuse_map("map_Kd -mm 0.1 0.9 -imfchan r file.png").channel, Some(TextureChannel::Red));
+	}
+
+	#[test]
+	fn texture_file_names_with_spaces() {
+		assert_eq!(diffuse_map("map_Kd my texture.png").file, "my texture.png");
+		assert_eq!(diffuse_map("map_Kd -clamp on my texture.png").file, "my texture.png");
+		assert_eq!(diffuse_map("map_Kd -o 1 2 my texture.png").offset, [ 1.0, 2.0, 0.0 ]);
+		assert_eq!(diffuse_map("map_Kd -o 1 2 my texture.png").file, "my texture.png");
+	}
+
+	#[test]
+	fn illumination_models() {
+		use self::IlluminationModel::*;
+
+		// Model, then whether it is lit, has specular highlights, reflections, ray tracing,
+		// transparency, refraction and Fresnel reflections
+		let table = [
+			(Constant, false, false, false, false, false, false, false),
+			(Diffuse, true, false, false, false, false, false, false),
+			(Highlight, true, true, false, false, false, false, false),
+			(Reflection, true, true, true, true, false, false, false),
+			(Glass, true, true, true, true, true, false, false),
+			(Fresnel, true, true, true, true, false, false, true),
+			(Refraction, true, true, true, true, true, true, false),
+			(FresnelRefraction, true, true, true, true, true, true, true),
+			(ReflectionMap, true, true, true, false, false, false, false),
+			(GlassReflectionMap, true, true, true, false, true, false, false),
+			(ShadowMatte, false, false, false, false, false, false, false),
+		];
+
+		for (number, &(model, lit, specular, reflection, ray_traced, transparency, refraction, fresnel)) in table.iter().enumerate() {
+			assert_eq!(IlluminationModel::from_number(number as u32), Some(model));
+			assert_eq!(model.number(), number as u32);
+			assert_eq!(
+				(model.lit(), model.ambient(), model.specular(), model.reflection(), model.ray_traced(), model.transparency(), model.refraction(), model.fresnel()),
+				(lit, lit, specular, reflection, ray_traced, transparency, refraction, fresnel),
+				"{:?}", model
+			);
+		}
+
+		assert_eq!(IlluminationModel::from_number(11), None);
+		assert_eq!(IlluminationModel::from_number(u32::MAX), None);
+		assert_eq!(IlluminationModel::default(), Highlight);
+	}
+
+	#[test]
+	fn illumination_model_statement() {
+		let options = MtlOptions { mode: ParseMode::Strict, ..MtlOptions::default() };
+		let (materials, _) = parse_with_options(b"newmtl test\nillum 10\n", &options).unwrap();
+
+		assert_eq!(materials["test"].illumination_model, IlluminationModel::ShadowMatte);
+
+		let error = parse_with_options(b"newmtl test\nillum 11\n", &options).unwrap_err();
+
+		assert!(matches!(error, WavefrontError::InvalidElement { .. }), "{:?}", error);
+		assert_eq!(error.location().map(|location| (location.line, location.column)), Some((2, 7)));
+	}
+}
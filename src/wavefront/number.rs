@@ -0,0 +1,146 @@
+use nom::{ IResult, ErrorKind, digit };
+use std::str;
+use std::f32;
+
+/// Parse a floating point number shared by the OBJ and MTL parsers.
+///
+/// Accepts an optional sign, digits with an optional fractional part (either side of the point
+/// may be empty, but not both), an optional exponent, and the special values `nan`, `inf` and
+/// `infinity` in any case. The number is converted in place without allocating.
+pub fn parse_float(input: &[u8]) -> IResult<&[u8], f32> {
+	let mut pos = 0;
+
+	if pos < input.len() && (input[pos] == b'+' || input[pos] == b'-') {
+		pos += 1;
+	}
+
+	if let Some((len, value)) = parse_special(&input[pos..]) {
+		let value = if input[0] == b'-' { -value } else { value };
+
+		return IResult::Done(&input[pos + len..], value);
+	}
+
+	let integer_digits = count_digits(&input[pos..]);
+	pos += integer_digits;
+
+	let mut fraction_digits = 0;
+
+	if pos < input.len() && input[pos] == b'.' {
+		fraction_digits = count_digits(&input[pos + 1..]);
+
+		// A lone "." is not a number, but "1." is
+		if integer_digits > 0 || fraction_digits > 0 {
+			pos += 1 + fraction_digits;
+		}
+	}
+
+	if integer_digits == 0 && fraction_digits == 0 {
+		return IResult::Error(error_position!(ErrorKind::Digit, input));
+	}
+
+	// Only consume an exponent if it has at least one digit, so "1e" parses as "1" followed by "e"
+	if pos < input.len() && (input[pos] == b'e' || input[pos] == b'E') {
+		let mut exponent = pos + 1;
+
+		if exponent < input.len() && (input[exponent] == b'+' || input[exponent] == b'-') {
+			exponent += 1;
+		}
+
+		let exponent_digits = count_digits(&input[exponent..]);
+
+		if exponent_digits > 0 {
+			pos = exponent + exponent_digits;
+		}
+	}
+
+	// The scanned bytes are all ASCII so both conversions succeed for anything Rust's float parser
+	// accepts, which is a superset of the grammar above
+	match str::from_utf8(&input[..pos]).ok().and_then(|num| num.parse::<f32>().ok()) {
+		Some(value) => IResult::Done(&input[pos..], value),
+		None => IResult::Error(error_position!(ErrorKind::Digit, input))
+	}
+}
+
+// An unsigned integer, shared by the OBJ and MTL parsers
+named!(pub parse_unsigned<u32>,
+	map_res!(digit, |num: &[u8]| str::from_utf8(num).map_err(|_| ()).and_then(|num| num.parse::<u32>().map_err(|_| ())))
+);
+
+fn count_digits(input: &[u8]) -> usize {
+	input.iter().take_while(|c| c.is_ascii_digit()).count()
+}
+
+fn parse_special(input: &[u8]) -> Option<(usize, f32)> {
+	let matches = |word: &[u8]| input.len() >= word.len() && input[..word.len()].eq_ignore_ascii_case(word);
+
+	if matches(b"infinity") {
+		Some((8, f32::INFINITY))
+	} else if matches(b"inf") {
+		Some((3, f32::INFINITY))
+	} else if matches(b"nan") {
+		Some((3, f32::NAN))
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The value parsed from the start of `input` and the input left over
+	fn parse(input: &str) -> Option<(f32, &str)> {
+		match parse_float(input.as_bytes()) {
+			IResult::Done(rest, value) => Some((value, str::from_utf8(rest).unwrap())),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn numbers() {
+		assert_eq!(parse("1"), Some((1.0, "")));
+		assert_eq!(parse("+2.25 "), Some((2.25, " ")));
+		assert_eq!(parse(".5"), Some((0.5, "")));
+		assert_eq!(parse("-.5"), Some((-0.5, "")));
+		assert_eq!(parse("1."), Some((1.0, "")));
+		assert_eq!(parse("-1e-3"), Some((-1e-3, "")));
+		assert_eq!(parse("2.5E+2\n"), Some((250.0, "\n")));
+		assert_eq!(parse("1.5.2"), Some((1.5, ".2")));
+	}
+
+	#[test]
+	fn special_values() {
+		let (value, rest) = parse("nan").unwrap();
+
+		assert!(value.is_nan());
+		assert_eq!(rest, "");
+
+		assert!(parse("-NaN").unwrap().0.is_nan());
+		assert_eq!(parse("inf"), Some((f32::INFINITY, "")));
+		assert_eq!(parse("-inf"), Some((f32::NEG_INFINITY, "")));
+		assert_eq!(parse("infinity"), Some((f32::INFINITY, "")));
+		assert_eq!(parse("+Infinity"), Some((f32::INFINITY, "")));
+	}
+
+	#[test]
+	fn exponent_without_digits() {
+		// The `e` is left for the statement parser to reject
+		assert_eq!(parse("1e"), Some((1.0, "e")));
+		assert_eq!(parse("1e+"), Some((1.0, "e+")));
+		assert_eq!(parse("1e-x"), Some((1.0, "e-x")));
+	}
+
+	#[test]
+	fn invalid() {
+		for input in &[ "", "--1", "+-1", ".", "-.", "+", "e5", ".e5", "x1", " 1" ] {
+			assert_eq!(parse(input), None, "{:?}", input);
+		}
+	}
+
+	#[test]
+	fn unsigned() {
+		assert_eq!(parse_unsigned(b"42 "), IResult::Done(&b" "[..], 42));
+		assert!(parse_unsigned(b"-1").is_err());
+		assert!(parse_unsigned(b"99999999999").is_err());
+	}
+}
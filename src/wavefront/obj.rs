@@ -1,344 +1,3945 @@
-use nom::{ space, digit, line_ending, IResult, not_line_ending };
+use nom::{ IResult, space, digit, line_ending, not_line_ending };
 use std::str;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::borrow::Cow;
+use std::thread;
+use std::panic;
 use std::path::Path;
-use std::error::Error;
+use std::sync::{ Arc, OnceLock };
+use std::collections::HashMap;
+use std::ops::Range;
 
-use super::mtl;
+use super::mtl::{ self, MtlOptions };
+use super::spectral::Illuminant;
+use super::error::{ WavefrontError, Warning, ParseMode, Location, token_column };
+use super::lines::{ self, Lines, parse_line };
+use super::number::{ parse_float, parse_unsigned };
+use super::triangulate::{ triangulate, newell_normal, normalize };
+use super::freeform::{ CurveEvaluator, SurfaceEvaluator, Region, TrimSpan, Seam, SeamSide, SharedPositions, tessellate_surface, trim_loop, seam, shared_key };
 
-#[derive(Debug, Clone)]
-struct Face {
-	vertices: [u32; 3],
-	normals: [u32; 3],
+/// A single corner of a face, in any of the `v`, `v/vt`, `v//vn` or `v/vt/vn` forms. Indices are
+/// zero-based and refer to the model's vertex, texture vertex and normal pools.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceVertex {
+	pub vertex: u32,
+	pub texcoord: Option<u32>,
+	pub normal: Option<u32>,
 }
 
-#[derive(Debug, Clone)]
-struct WavefrontObject {
-	name: String,
-	material_name: Option<String>,
-	smoothing: Option<bool>,
-	faces: Vec<Face>,
+/// Face vertex as written in the file. Indices are one-based, or negative to count back from the
+/// most recently declared element.
+type RawFaceVertex = (i64, Option<i64>, Option<i64>);
+
+/// `curv` statement as written: the parameter range and vertex references
+type RawCurve = ((f32, f32), Vec<i64>);
+
+/// `surf` statement as written: the u and v parameter ranges and control vertex references
+type RawSurface = ((f32, f32), (f32, f32), Vec<RawFaceVertex>);
+
+/// A `u0 u1 curv2d` triple from a `trim`, `hole` or `scrv` statement
+type RawCurveSegment = (f32, f32, i64);
+/// Surface index, start and end parameters and 2D curve index of one side of a `con` statement
+type RawConnectedCurve = (i64, f32, f32, i64);
+
+/// Highest degree the spec allows for free-form curves and surfaces
+const MAX_DEGREE: u32 = 20;
+
+/// A polygon of three or more vertices
+#[derive(Debug, Clone, PartialEq)]
+pub struct Face {
+	vertices: Vec<FaceVertex>,
+	groups: Arc<[String]>,
+	smoothing_group: u32,
 }
 
-#[derive(Debug)]
-pub struct WavefrontModelData {
-	objects: Vec<WavefrontObject>,
-	vertices: Vec<[f32; 3]>,
-	normals: Vec<[f32; 3]>,
-	texcoords: Vec<[f32; 3]>,
+impl Face {
+	/// Smoothing group set by the last `s` statement before this face. `0` means smoothing is off,
+	/// and `s on` is treated as group `1`.
+	pub fn smoothing_group(&self) -> u32 {
+		self.smoothing_group
+	}
+
+	/// Names of the groups set by the last `g` statement before this face. Faces before any `g`
+	/// statement are in the group `default`.
+	pub fn groups(&self) -> &[String] {
+		&self.groups
+	}
+
+	pub fn in_group(&self, group: &str) -> bool {
+		self.groups.iter().any(|name| name == group)
+	}
+
+	/// Corners of the polygon in winding order. Every corner uses the same index form.
+	pub fn vertices(&self) -> &[FaceVertex] {
+		&self.vertices
+	}
+
+	/// Whether the face has texture vertex indices
+	pub fn has_texcoords(&self) -> bool {
+		self.vertices[0].texcoord.is_some()
+	}
+
+	/// Whether the face has normal indices
+	pub fn has_normals(&self) -> bool {
+		self.vertices[0].normal.is_some()
+	}
+
+	/// Check the face is well formed, returning the position of the statement token at fault
+	/// otherwise
+	fn validate(&self) -> Result<(), (usize, String)> {
+		if self.vertices.len() < 3 {
+			return Err((0, format!("Face has {} vertices, at least 3 are required", self.vertices.len())));
+		}
+
+		let form = |v: &FaceVertex| (v.texcoord.is_some(), v.normal.is_some());
+		let first = form(&self.vertices[0]);
+
+		match self.vertices.iter().position(|v| form(v) != first) {
+			Some(position) => Err((position + 1, format!(
+				"Face mixes vertex index forms: vertex 1 is {} but vertex {} is {}",
+				describe_form(first), position + 1, describe_form(form(&self.vertices[position]))
+			))),
+			None => Ok(())
+		}
+	}
 }
 
-#[derive(Debug)]
-pub struct WavefrontModel {
-	materials: Option<mtl::WavefrontMaterials>,
-	objects: Vec<WavefrontObject>,
-	vertices: Vec<[f32; 3]>,
-	normals: Vec<[f32; 3]>,
-	texcoords: Vec<[f32; 3]>,
+/// A corner of a line element, in the `v` or `v/vt` form
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineVertex {
+	pub vertex: u32,
+	pub texcoord: Option<u32>,
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct BufferVertex {
-    position: [ f32; 3 ],
-	normal: [ f32; 3 ],
+/// A polyline through two or more vertices
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+	vertices: Vec<LineVertex>,
+	groups: Arc<[String]>,
 }
 
-implement_vertex!(BufferVertex, position, normal);
+impl Line {
+	pub fn vertices(&self) -> &[LineVertex] {
+		&self.vertices
+	}
 
-impl WavefrontModel {
-	pub fn to_vertices(&self) -> Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)> {
-		self.objects.iter().map(|ref object| {
-			let vertices = object.faces.iter().flat_map(|f| {
-				let v1 = self.vertices.get(f.vertices[0] as usize).expect(&format!("Could not get v1 {}", f.vertices[0]));
-				let v2 = self.vertices.get(f.vertices[1] as usize).expect(&format!("Could not get v2 {}", f.vertices[1]));
-				let v3 = self.vertices.get(f.vertices[2] as usize).expect(&format!("Could not get v3 {}", f.vertices[2]));
-
-				let vn1 = self.normals.get(f.normals[0] as usize).expect(&format!("Could not get vn1 {}", f.normals[0]));
-				let vn2 = self.normals.get(f.normals[1] as usize).expect(&format!("Could not get vn2 {}", f.normals[1]));
-				let vn3 = self.normals.get(f.normals[2] as usize).expect(&format!("Could not get vn3 {}", f.normals[2]));
-
-				vec![
-					BufferVertex { position: *v1, normal: *vn1 },
-					BufferVertex { position: *v2, normal: *vn2 },
-					BufferVertex { position: *v3, normal: *vn3 },
-				]
-			}).collect();
-
-			let mat = match object.material_name {
-				Some(ref mat) => (*mat).clone(),
-				None => String::from("Material")
-			};
+	/// Names of the groups set by the last `g` statement before this line
+	pub fn groups(&self) -> &[String] {
+		&self.groups
+	}
 
-			let material = match &self.materials {
-				&Some(ref materials) => {
-					match &materials.get(&mat) {
-						&Some(ref mat) => (*mat).clone(),
-						&None => mtl::WavefrontMaterial {
-							name: String::from("Default material"),
-							specular_exponent: 1.0,
-							ambient: [1.0, 0.0, 0.0],
-							diffuse: [1.0, 0.0, 0.0],
-							specular: [0.7, 0.7, 0.7],
-						}
-					}
-				},
-				&None => mtl::WavefrontMaterial {
-					name: String::from("Default material"),
-					specular_exponent: 1.0,
-					ambient: [1.0, 0.0, 0.0],
-					diffuse: [1.0, 0.0, 0.0],
-					specular: [0.7, 0.7, 0.7],
-				}
-			};
+	pub fn in_group(&self, group: &str) -> bool {
+		self.groups.iter().any(|name| name == group)
+	}
+
+	/// Check the line is well formed, returning the position of the statement token at fault
+	/// otherwise
+	fn validate(&self) -> Result<(), (usize, String)> {
+		if self.vertices.len() < 2 {
+			return Err((0, format!("Line has {} vertices, at least 2 are required", self.vertices.len())));
+		}
+
+		let has_texcoord = self.vertices[0].texcoord.is_some();
 
-			(vertices, material)
-		}).collect()
+		match self.vertices.iter().position(|v| v.texcoord.is_some() != has_texcoord) {
+			Some(position) => Err((position + 1, format!(
+				"Line mixes vertex index forms: vertex 1 is {} but vertex {} is {}",
+				describe_form((has_texcoord, false)), position + 1, describe_form((!has_texcoord, false))
+			))),
+			None => Ok(())
+		}
 	}
 }
 
-named!(negative, tag!("-"));
+/// A point element. One `p` statement may list several vertices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+	vertices: Vec<u32>,
+	groups: Arc<[String]>,
+}
 
-named!(decimal, complete!(chain!(
-    tag!(".")  ~
-    val: digit ,
-    || val
-)));
+impl Point {
+	pub fn vertices(&self) -> &[u32] {
+		&self.vertices
+	}
 
-named!(parse_float<f32>,
-	do_parse!(
-		sign: opt!(negative) >>
-		bef: digit >>
-		aft: opt!(decimal) >>
-		({
-			let a = match sign {
-				Some(sign) => str::from_utf8(sign).unwrap(),
-				None => ""
-			};
+	/// Names of the groups set by the last `g` statement before this point
+	pub fn groups(&self) -> &[String] {
+		&self.groups
+	}
 
-			let b = str::from_utf8(bef).unwrap();
+	pub fn in_group(&self, group: &str) -> bool {
+		self.groups.iter().any(|name| name == group)
+	}
+}
 
-			let c = match aft {
-				Some(aft) => str::from_utf8(aft).unwrap(),
-				None => ""
-			};
+/// Basis of a free-form curve or surface, set by the `cstype` statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BasisType {
+	BasisMatrix,
+	Bezier,
+	BSpline,
+	Cardinal,
+	Taylor,
+}
 
-			format!("{}{}.{}", a, b, c).parse::<f32>().unwrap()
-		})
-	)
-);
+impl BasisType {
+	/// Name of the type as written in `cstype`
+	pub fn name(&self) -> &'static str {
+		match *self {
+			BasisType::BasisMatrix => "bmatrix",
+			BasisType::Bezier => "bezier",
+			BasisType::BSpline => "bspline",
+			BasisType::Cardinal => "cardinal",
+			BasisType::Taylor => "taylor",
+		}
+	}
+}
 
-named!(parse_face_index<u32>,
-	do_parse!(num: digit >> (str::from_utf8(num).unwrap().parse::<u32>().unwrap() - 1))
-);
+/// The basis of one parametric direction of a free-form curve or surface
+#[derive(Debug, Clone, PartialEq)]
+pub struct Basis {
+	/// Polynomial degree from `deg`. Always 3 for Cardinal splines.
+	pub degree: u32,
+	/// Basis matrix from `bmat` with `(degree + 1)²` entries, column index varying fastest. Only
+	/// set for `bmatrix`.
+	pub matrix: Option<Vec<f32>>,
+	/// Step size from `step`. Only set for `bmatrix`.
+	pub step: Option<u32>,
+	/// Global parameter values from `parm`, or the knot vector for B-splines
+	pub parameters: Vec<f32>,
+}
 
-named!(parse_vector3<&[u8], [f32; 3]>,
-	do_parse!(
-		x: parse_float >>
-		space >>
-		y: parse_float >>
-		space >>
-		z: parse_float >>
-		line_ending >>
-		([ x, y, z ])
-	)
-);
+/// How curves are approximated by line segments, set by the `ctech` statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurveTechnique {
+	/// `cparm`: each polynomial segment is split into `resolution * degree` pieces of equal
+	/// parameter length. A resolution of 0 gives one line segment per polynomial segment.
+	Parametric { resolution: f32 },
+	/// `cspace`: line segments are at most `max_length` long in real space
+	Spatial { max_length: f32 },
+	/// `curv`: segments are split until they are within `max_distance` of the curve and the
+	/// tangents at their ends differ by at most `max_angle` degrees
+	Curvature { max_distance: f32, max_angle: f32 },
+}
 
-named!(vertex <&[u8], [f32; 3]>, do_parse!(tag!("v") >> space >> vector: parse_vector3 >> (vector)));
-named!(normal <&[u8], [f32; 3]>, do_parse!(tag!("vn") >> space >> vector: parse_vector3 >> (vector)));
-named!(texcoord<&[u8], [f32; 3]>,
-	do_parse!(
-		tag!("vt") >>
-		space >>
-		u: parse_float >>
-		space >>
-		v: parse_float >>
-		opt!(space) >>
-		w: opt!(parse_float) >>
-		([ u, v, w.unwrap_or(0.0) ])
-	)
-);
-named!(face <&[u8], Face>,
-	do_parse!(
-		tag!("f") >>
-		space >>
-		v1: parse_face_index >> tag!("/") >> vt1: opt!(parse_face_index) >> tag!("/") >> vn1: parse_face_index >>
-		space >>
-		v2: parse_face_index >> tag!("/") >> vt2: opt!(parse_face_index) >> tag!("/") >> vn2: parse_face_index >>
-		space >>
-		v3: parse_face_index >> tag!("/") >> vt3: opt!(parse_face_index) >> tag!("/") >> vn3: parse_face_index >>
-		line_ending >>
-		(Face {
-			vertices: [ v1, v2, v3 ],
-			normals: [ vn1, vn2, vn3 ],
-		})
-	)
-);
+impl Default for CurveTechnique {
+	fn default() -> CurveTechnique {
+		CurveTechnique::Parametric { resolution: 4.0 }
+	}
+}
 
-named!(mtllib<&[u8], String>,
-	do_parse!(
-		tag!("mtllib") >>
-		space >>
-		libname: not_line_ending >>
-		line_ending >>
-		(String::from(str::from_utf8(libname).unwrap_or("")))
-	)
-);
+impl CurveTechnique {
+	fn validate(&self) -> Result<(), String> {
+		match *self {
+			CurveTechnique::Parametric { resolution } if resolution < 0.0 || resolution.is_nan() => {
+				Err(format!("Resolution {} can't be negative", resolution))
+			},
+			CurveTechnique::Spatial { max_length } if max_length <= 0.0 || max_length.is_nan() => {
+				Err(format!("Maximum length {} must be greater than 0", max_length))
+			},
+			CurveTechnique::Curvature { max_distance, max_angle } if !(max_distance > 0.0 || max_angle > 0.0) => {
+				Err(String::from("At least one of the maximum distance and angle must be greater than 0"))
+			},
+			_ => Ok(())
+		}
+	}
+}
 
-named!(usemtl<&[u8], String>,
-	do_parse!(
-		tag!("usemtl") >>
-		space >>
-		material: not_line_ending >>
-		line_ending >>
-		(String::from(str::from_utf8(material).unwrap_or("")))
-	)
-);
+/// How surfaces are approximated by triangles, set by the `stech` statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SurfaceTechnique {
+	/// `cparma`: each patch is split into `resolution * degree` pieces of equal parameter length
+	/// in each direction. Resolutions of 0 give two triangles per patch.
+	Parametric { u_resolution: f32, v_resolution: f32 },
+	/// `cparmb`: like `cparma` with the same resolution in both directions
+	ParametricRefined { resolution: f32 },
+	/// `cspace`: the surface is split until rectangle edges are at most `max_length` long in
+	/// real space
+	Spatial { max_length: f32 },
+	/// `curv`: the surface is split until rectangles are within `max_distance` of the surface and
+	/// normals at their corners differ by at most `max_angle` degrees
+	Curvature { max_distance: f32, max_angle: f32 },
+}
 
-named!(smoothing<&[u8], bool>,
-	do_parse!(
-		tag!("s") >>
-		space >>
-		state: alt!(tag!("on") | tag!("off")) >>
-		line_ending >>
-		(match str::from_utf8(state).unwrap_or("").as_ref() {
-			"off" => false,
-			"on" => true,
-			_ => false
-		})
-	)
-);
+impl Default for SurfaceTechnique {
+	fn default() -> SurfaceTechnique {
+		SurfaceTechnique::Parametric { u_resolution: 4.0, v_resolution: 4.0 }
+	}
+}
 
-named!(object_start<&[u8], String>,
-	do_parse!(
-		tag!("o") >>
-		space >>
-		name: take_until_and_consume!("\n") >>
-		(String::from(str::from_utf8(name).unwrap_or("<invalid object name>")))
-	)
-);
+impl SurfaceTechnique {
+	fn validate(&self) -> Result<(), String> {
+		match *self {
+			SurfaceTechnique::Parametric { u_resolution, v_resolution } => {
+				CurveTechnique::Parametric { resolution: u_resolution }.validate()?;
+				CurveTechnique::Parametric { resolution: v_resolution }.validate()
+			},
+			SurfaceTechnique::ParametricRefined { resolution } => CurveTechnique::Parametric { resolution }.validate(),
+			SurfaceTechnique::Spatial { max_length } => CurveTechnique::Spatial { max_length }.validate(),
+			SurfaceTechnique::Curvature { max_distance, max_angle } => CurveTechnique::Curvature { max_distance, max_angle }.validate(),
+		}
+	}
+}
 
-#[derive(Debug)]
-enum FileEntity {
-	Vertex([f32; 3]),
-	Normal([f32; 3]),
-	TexCoord([f32; 3]),
-	Face(Face),
-	Material(String),
-	Smoothing(bool),
-	Object(String),
-	MatLib(String),
-	Ignore
+/// A span of a 2D curve used in a trimming loop, hole or special curve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveSegment {
+	/// Parameter value the span starts at
+	pub start: f32,
+	/// Parameter value the span ends at
+	pub end: f32,
+	/// Zero-based index into the model's 2D curves
+	pub curve: u32,
 }
 
-named!(entity<&[u8], FileEntity>, alt!(
-	vertex => { |v| FileEntity::Vertex(v) } |
-	normal => { |n| FileEntity::Normal(n) } |
-	face => { |f| FileEntity::Face(f) } |
-	texcoord => { |t| FileEntity::TexCoord(t) } |
-	usemtl => { |m| FileEntity::Material(m) } |
-	smoothing => { |s| FileEntity::Smoothing(s) } |
-	mtllib => { |m| FileEntity::MatLib(m) } |
-	object_start => { |o| FileEntity::Object(String::from(o)) } |
-
-	take_until_and_consume!("\n") => { |_| FileEntity::Ignore }
-));
-
-named!(file<&[u8], Vec<FileEntity>>, many1!(entity));
-
-fn parse(input: &[u8]) -> Result<(WavefrontModelData, Option<String>), String> {
-
-
-	match file(input) {
-		IResult::Done(_, lines) => {
-			let mut vertices = Vec::new();
-			let mut normals = Vec::new();
-			let mut texcoords = Vec::new();
-			let mut objects = Vec::new();
-			let mut mtl_lib: Option<String> = None;
-
-			for line in lines.into_iter() {
-				match line {
-					FileEntity::Vertex(ref v) => vertices.push(*v),
-					FileEntity::Normal(ref n) => normals.push(*n),
-					FileEntity::TexCoord(ref t) => texcoords.push(*t),
-					FileEntity::Object(ref o) => {
-						objects.push(WavefrontObject {
-							name: (*o).clone(),
-							material_name: None,
-							smoothing: None,
-							faces: Vec::new(),
-						})
-					},
-					FileEntity::MatLib(ref m_filename) => mtl_lib = Some((*m_filename).clone()),
-					FileEntity::Face(ref f) => { objects.last_mut().unwrap().faces.push((*f).clone()) },
-					FileEntity::Material(ref m) => { objects.last_mut().	unwrap().material_name = Some((*m).clone()) },
-					FileEntity::Smoothing(ref s) => { objects.last_mut().unwrap().smoothing = Some(*s) },
-					FileEntity::Ignore => (),
-				}
-			}
+/// A region of a surface kept by trimming: an outer loop with any number of holes cut from it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrimRegion {
+	/// Outer loop from `trim`. `None` when holes are given without a `trim`, in which case the
+	/// surface's parameter range is the outer boundary.
+	pub outer: Option<Vec<CurveSegment>>,
+	/// Inner loops from the `hole` statements that follow the `trim`
+	pub holes: Vec<Vec<CurveSegment>>,
+}
 
-			Ok((WavefrontModelData {
-				vertices: vertices,
-				normals: normals,
-				texcoords: texcoords,
-				objects: objects,
-			}, mtl_lib))
-		},
-		IResult::Incomplete(need) => {
-			Err(format!("Incomplete, {:?}", need))
-		},
-		IResult::Error(err) => {
-			Err(format!("Some error: {:?}", err))
-		}
+impl TrimRegion {
+	/// Whether any loop of the region includes a span of a 2D curve
+	pub fn uses_curve(&self, curve: u32) -> bool {
+		self.outer.iter().chain(self.holes.iter()).flat_map(|segments| segments.iter()).any(|segment| segment.curve == curve)
 	}
 }
 
-pub fn load(pathname: &str) -> Result<WavefrontModel, String> {
-	let path = Path::new(pathname);
-    let display = path.display();
+/// One side of a connection: the span of a trimming curve along which a surface meets another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectedCurve {
+	/// Zero-based index of the surface among all of the model's surfaces, in file order
+	pub surface: usize,
+	/// Parameter value on the curve the connection starts at
+	pub start: f32,
+	/// Parameter value on the curve the connection ends at
+	pub end: f32,
+	/// Zero-based index into the model's 2D curves
+	pub curve: u32,
+}
 
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("couldn't open {}: {}", display, why.description()),
-        Ok(file) => file,
-    };
+/// Two surfaces joined along their trimming curves by a `con` statement. The two spans map to the
+/// same curve in space, with the starts and ends meeting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Connection {
+	pub first: ConnectedCurve,
+	pub second: ConnectedCurve,
+}
 
-    let mut s = String::new();
+/// A free-form space curve from a `curv` statement and its body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+	basis_type: BasisType,
+	rational: bool,
+	basis: Basis,
+	range: (f32, f32),
+	control_points: Vec<u32>,
+	special_points: Vec<u32>,
+	technique: CurveTechnique,
+	groups: Arc<[String]>,
+}
 
-    match file.read_to_string(&mut s) {
-        Err(why) => panic!("couldn't read {}: {}", display, why.description()),
-        Ok(_) => ()
-    }
+impl Curve {
+	pub fn basis_type(&self) -> BasisType {
+		self.basis_type
+	}
 
-	let (model, mtllib) = parse(&s.as_bytes()).unwrap();
+	/// Whether the curve was declared `cstype rat`, so control point weights apply
+	pub fn is_rational(&self) -> bool {
+		self.rational
+	}
 
-	let materials = match mtllib {
-		Some(mtl_filename) => {
-			let mtl_path = path.with_file_name(mtl_filename);
-			let mtl_display = mtl_path.display();
+	pub fn basis(&self) -> &Basis {
+		&self.basis
+	}
 
-			let mut mtl_file = match File::open(&mtl_path) {
-				Err(why) => panic!("couldn't open {}: {}", mtl_display, why.description()),
-				Ok(file) => file,
-			};
+	/// Global parameter range the curve is evaluated over
+	pub fn range(&self) -> (f32, f32) {
+		self.range
+	}
 
-			let mut mtl_s = String::new();
+	/// Approximation technique set by the last `ctech` statement before the curve
+	pub fn technique(&self) -> CurveTechnique {
+		self.technique
+	}
 
-			match mtl_file.read_to_string(&mut mtl_s) {
-				Err(why) => panic!("couldn't read {}: {}", mtl_display, why.description()),
-				Ok(_) => ()
-			}
+	/// Zero-based indices into the model's vertices
+	pub fn control_points(&self) -> &[u32] {
+		&self.control_points
+	}
 
-			Some(mtl::parse(&mtl_s.as_bytes()).unwrap())
-		},
-		None => None
-	};
+	/// Zero-based indices into the model's parameter vertices, from `sp`
+	pub fn special_points(&self) -> &[u32] {
+		&self.special_points
+	}
 
-	Ok(WavefrontModel {
-		materials: materials,
-		vertices: model.vertices,
-		normals: model.normals,
-		texcoords: model.texcoords,
-		objects: model.objects,
-	})
-}
\ No newline at end of file
+	/// Names of the groups set by the last `g` statement before this curve
+	pub fn groups(&self) -> &[String] {
+		&self.groups
+	}
+
+	pub fn in_group(&self, group: &str) -> bool {
+		self.groups.iter().any(|name| name == group)
+	}
+}
+
+/// A curve in the parameter space of a surface from a `curv2` statement, used for trimming,
+/// special curves and connectivity
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve2 {
+	basis_type: BasisType,
+	rational: bool,
+	basis: Basis,
+	control_points: Vec<u32>,
+	special_points: Vec<u32>,
+}
+
+impl Curve2 {
+	pub fn basis_type(&self) -> BasisType {
+		self.basis_type
+	}
+
+	/// Whether the curve was declared `cstype rat`, so the parameter vertex weights apply
+	pub fn is_rational(&self) -> bool {
+		self.rational
+	}
+
+	pub fn basis(&self) -> &Basis {
+		&self.basis
+	}
+
+	/// Zero-based indices into the model's parameter vertices
+	pub fn control_points(&self) -> &[u32] {
+		&self.control_points
+	}
+
+	/// Zero-based indices into the model's parameter vertices, from `sp`
+	pub fn special_points(&self) -> &[u32] {
+		&self.special_points
+	}
+}
+
+/// A free-form surface from a `surf` statement and its body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Surface {
+	basis_type: BasisType,
+	rational: bool,
+	basis_u: Basis,
+	basis_v: Basis,
+	range_u: (f32, f32),
+	range_v: (f32, f32),
+	size: (usize, usize),
+	control_points: Vec<FaceVertex>,
+	trim_regions: Vec<TrimRegion>,
+	special_curves: Vec<Vec<CurveSegment>>,
+	special_points: Vec<u32>,
+	technique: SurfaceTechnique,
+	curve_technique: CurveTechnique,
+	groups: Arc<[String]>,
+}
+
+impl Surface {
+	pub fn basis_type(&self) -> BasisType {
+		self.basis_type
+	}
+
+	/// Whether the surface was declared `cstype rat`, so control point weights apply
+	pub fn is_rational(&self) -> bool {
+		self.rational
+	}
+
+	pub fn basis_u(&self) -> &Basis {
+		&self.basis_u
+	}
+
+	pub fn basis_v(&self) -> &Basis {
+		&self.basis_v
+	}
+
+	/// Global parameter range the surface is evaluated over in the u direction
+	pub fn range_u(&self) -> (f32, f32) {
+		self.range_u
+	}
+
+	/// Global parameter range the surface is evaluated over in the v direction
+	pub fn range_v(&self) -> (f32, f32) {
+		self.range_v
+	}
+
+	/// Approximation technique set by the last `stech` statement before the surface
+	pub fn technique(&self) -> SurfaceTechnique {
+		self.technique
+	}
+
+	/// Approximation technique for the surface's trimming loops, holes and special curves, set by
+	/// the last `ctech` statement before the surface
+	pub fn curve_technique(&self) -> CurveTechnique {
+		self.curve_technique
+	}
+
+	/// Number of control points in the u and v directions
+	pub fn size(&self) -> (usize, usize) {
+		self.size
+	}
+
+	/// Control points with u varying fastest, so the point at `(i, j)` is at `j * size().0 + i`
+	pub fn control_points(&self) -> &[FaceVertex] {
+		&self.control_points
+	}
+
+	/// Trimmed regions in file order. Empty if the surface is only trimmed at its parameter range.
+	pub fn trim_regions(&self) -> &[TrimRegion] {
+		&self.trim_regions
+	}
+
+	/// Curves from `scrv` that must appear as edges in any tessellation
+	pub fn special_curves(&self) -> &[Vec<CurveSegment>] {
+		&self.special_curves
+	}
+
+	/// Zero-based indices into the model's parameter vertices, from `sp`
+	pub fn special_points(&self) -> &[u32] {
+		&self.special_points
+	}
+
+	/// Names of the groups set by the last `g` statement before this surface
+	pub fn groups(&self) -> &[String] {
+		&self.groups
+	}
+
+	pub fn in_group(&self, group: &str) -> bool {
+		self.groups.iter().any(|name| name == group)
+	}
+}
+
+fn describe_form(form: (bool, bool)) -> &'static str {
+	match form {
+		(false, false) => "v",
+		(true, false) => "v/vt",
+		(false, true) => "v//vn",
+		(true, true) => "v/vt/vn",
+	}
+}
+
+/// A contiguous run of an object's elements that share a material
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavefrontMesh {
+	material_name: Option<String>,
+	faces: Range<usize>,
+	lines: Range<usize>,
+	points: Range<usize>,
+	curves: Range<usize>,
+	surfaces: Range<usize>,
+}
+
+impl WavefrontMesh {
+	/// Name of the material set by `usemtl`, if any
+	pub fn material_name(&self) -> Option<&str> {
+		self.material_name.as_deref()
+	}
+
+	/// Range of the mesh's faces within `WavefrontObject::faces`
+	pub fn face_range(&self) -> Range<usize> {
+		self.faces.clone()
+	}
+
+	/// Range of the mesh's lines within `WavefrontObject::lines`
+	pub fn line_range(&self) -> Range<usize> {
+		self.lines.clone()
+	}
+
+	/// Range of the mesh's points within `WavefrontObject::points`
+	pub fn point_range(&self) -> Range<usize> {
+		self.points.clone()
+	}
+
+	/// Range of the mesh's free-form curves within `WavefrontObject::curves`
+	pub fn curve_range(&self) -> Range<usize> {
+		self.curves.clone()
+	}
+
+	/// Range of the mesh's free-form surfaces within `WavefrontObject::surfaces`
+	pub fn surface_range(&self) -> Range<usize> {
+		self.surfaces.clone()
+	}
+}
+
+/// An object started by an `o` statement and the elements that follow it
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavefrontObject {
+	name: String,
+	meshes: Vec<WavefrontMesh>,
+	faces: Vec<Face>,
+	lines: Vec<Line>,
+	points: Vec<Point>,
+	curves: Vec<Curve>,
+	surfaces: Vec<Surface>,
+}
+
+impl WavefrontObject {
+	fn new(name: String) -> WavefrontObject {
+		WavefrontObject {
+			name,
+			meshes: Vec::new(),
+			faces: Vec::new(),
+			lines: Vec::new(),
+			points: Vec::new(),
+			curves: Vec::new(),
+			surfaces: Vec::new(),
+		}
+	}
+
+	/// The mesh new elements with the given material are added to, starting a new one if the
+	/// material differs from the previous element's
+	fn mesh(&mut self, material_name: &Option<String>) -> &mut WavefrontMesh {
+		let starts_new = match self.meshes.last() {
+			Some(mesh) => mesh.material_name != *material_name,
+			None => true,
+		};
+
+		if starts_new {
+			let (faces, lines, points) = (self.faces.len(), self.lines.len(), self.points.len());
+			let (curves, surfaces) = (self.curves.len(), self.surfaces.len());
+
+			self.meshes.push(WavefrontMesh {
+				material_name: material_name.clone(),
+				faces: faces..faces,
+				lines: lines..lines,
+				points: points..points,
+				curves: curves..curves,
+				surfaces: surfaces..surfaces,
+			});
+		}
+
+		self.meshes.last_mut().unwrap()
+	}
+
+	fn push_face(&mut self, face: Face, material_name: &Option<String>) {
+		let end = self.faces.len() + 1;
+
+		self.mesh(material_name).faces.end = end;
+		self.faces.push(face);
+	}
+
+	fn push_line(&mut self, line: Line, material_name: &Option<String>) {
+		let end = self.lines.len() + 1;
+
+		self.mesh(material_name).lines.end = end;
+		self.lines.push(line);
+	}
+
+	fn push_point(&mut self, point: Point, material_name: &Option<String>) {
+		let end = self.points.len() + 1;
+
+		self.mesh(material_name).points.end = end;
+		self.points.push(point);
+	}
+
+	fn push_curve(&mut self, curve: Curve, material_name: &Option<String>) {
+		let end = self.curves.len() + 1;
+
+		self.mesh(material_name).curves.end = end;
+		self.curves.push(curve);
+	}
+
+	fn push_surface(&mut self, surface: Surface, material_name: &Option<String>) {
+		let end = self.surfaces.len() + 1;
+
+		self.mesh(material_name).surfaces.end = end;
+		self.surfaces.push(surface);
+	}
+
+	/// Add the elements of the same object read from a later part of the file, continuing the last
+	/// mesh if the first of the other object's meshes has the same material
+	fn append(&mut self, other: WavefrontObject) {
+		let shift = |range: Range<usize>, offset: usize| range.start + offset..range.end + offset;
+		let (faces, lines, points) = (self.faces.len(), self.lines.len(), self.points.len());
+		let (curves, surfaces) = (self.curves.len(), self.surfaces.len());
+
+		for mesh in other.meshes {
+			let mesh = WavefrontMesh {
+				material_name: mesh.material_name,
+				faces: shift(mesh.faces, faces),
+				lines: shift(mesh.lines, lines),
+				points: shift(mesh.points, points),
+				curves: shift(mesh.curves, curves),
+				surfaces: shift(mesh.surfaces, surfaces),
+			};
+
+			match self.meshes.last_mut() {
+				Some(last) if last.material_name == mesh.material_name => {
+					last.faces.end = mesh.faces.end;
+					last.lines.end = mesh.lines.end;
+					last.points.end = mesh.points.end;
+					last.curves.end = mesh.curves.end;
+					last.surfaces.end = mesh.surfaces.end;
+				},
+				_ => self.meshes.push(mesh),
+			}
+		}
+
+		self.faces.extend(other.faces);
+		self.lines.extend(other.lines);
+		self.points.extend(other.points);
+		self.curves.extend(other.curves);
+		self.surfaces.extend(other.surfaces);
+	}
+
+	fn is_empty(&self) -> bool {
+		self.faces.is_empty() && self.lines.is_empty() && self.points.is_empty() &&
+			self.curves.is_empty() && self.surfaces.is_empty()
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Material sub-meshes in file order. An object that switches material several times has one
+	/// mesh per switch.
+	pub fn meshes(&self) -> &[WavefrontMesh] {
+		&self.meshes
+	}
+
+	pub fn faces(&self) -> &[Face] {
+		&self.faces
+	}
+
+	pub fn lines(&self) -> &[Line] {
+		&self.lines
+	}
+
+	pub fn points(&self) -> &[Point] {
+		&self.points
+	}
+
+	/// Free-form space curves from `curv` statements
+	pub fn curves(&self) -> &[Curve] {
+		&self.curves
+	}
+
+	/// Free-form surfaces from `surf` statements
+	pub fn surfaces(&self) -> &[Surface] {
+		&self.surfaces
+	}
+
+	pub fn mesh_faces(&self, mesh: &WavefrontMesh) -> &[Face] {
+		&self.faces[mesh.face_range()]
+	}
+
+	pub fn mesh_lines(&self, mesh: &WavefrontMesh) -> &[Line] {
+		&self.lines[mesh.line_range()]
+	}
+
+	pub fn mesh_points(&self, mesh: &WavefrontMesh) -> &[Point] {
+		&self.points[mesh.point_range()]
+	}
+
+	pub fn mesh_curves(&self, mesh: &WavefrontMesh) -> &[Curve] {
+		&self.curves[mesh.curve_range()]
+	}
+
+	pub fn mesh_surfaces(&self, mesh: &WavefrontMesh) -> &[Surface] {
+		&self.surfaces[mesh.surface_range()]
+	}
+}
+
+/// Geometry parsed from an OBJ file, before its material libraries are loaded
+#[derive(Debug, Default, PartialEq)]
+pub struct WavefrontModelData {
+	objects: Vec<WavefrontObject>,
+	vertices: Vec<[f32; 3]>,
+	weights: Vec<f32>,
+	normals: Vec<[f32; 3]>,
+	texcoords: Vec<[f32; 3]>,
+	parameter_vertices: Vec<[f32; 3]>,
+	curves_2d: Vec<Curve2>,
+	connections: Vec<Connection>,
+	warnings: Vec<Warning>,
+}
+
+impl WavefrontModelData {
+	pub fn objects(&self) -> &[WavefrontObject] {
+		&self.objects
+	}
+
+	pub fn vertices(&self) -> &[[f32; 3]] {
+		&self.vertices
+	}
+
+	pub fn normals(&self) -> &[[f32; 3]] {
+		&self.normals
+	}
+
+	/// Weights of the vertices, from their optional fourth coordinate. Defaults to 1.
+	pub fn weights(&self) -> &[f32] {
+		&self.weights
+	}
+
+	/// Texture vertices as `[u, v, w]`, with `w` defaulting to 0
+	pub fn texcoords(&self) -> &[[f32; 3]] {
+		&self.texcoords
+	}
+
+	/// Parameter vertices from `vp` as `[u, v, w]`, with `v` defaulting to 0 and `w` to 1
+	pub fn parameter_vertices(&self) -> &[[f32; 3]] {
+		&self.parameter_vertices
+	}
+
+	/// Curves in the parameter space of a surface from `curv2` statements
+	pub fn curves_2d(&self) -> &[Curve2] {
+		&self.curves_2d
+	}
+
+	/// Connections between surfaces from `con` statements
+	pub fn connections(&self) -> &[Connection] {
+		&self.connections
+	}
+
+	/// Statements that were ignored or skipped while parsing
+	pub fn warnings(&self) -> &[Warning] {
+		&self.warnings
+	}
+
+	/// Generate normals for every face that doesn't have them, adding them to the normal pool.
+	/// Parsing leaves faces as written.
+	pub fn generate_normals(&mut self) {
+		generate_normals(&mut self.objects, &self.vertices, &mut self.normals);
+	}
+}
+
+/// A loaded OBJ file with its materials
+#[derive(Debug)]
+pub struct WavefrontModel {
+	materials: Option<mtl::WavefrontMaterials>,
+	objects: Vec<WavefrontObject>,
+	vertices: Vec<[f32; 3]>,
+	weights: Vec<f32>,
+	normals: Vec<[f32; 3]>,
+	texcoords: Vec<[f32; 3]>,
+	parameter_vertices: Vec<[f32; 3]>,
+	curves_2d: Vec<Curve2>,
+	connections: Vec<Connection>,
+	warnings: Vec<Warning>,
+	seams: OnceLock<Vec<([ConnectedCurve; 2], Seam)>>,
+}
+
+/// A vertex ready to be uploaded to a vertex buffer. Line and point vertices have a zero normal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BufferVertex {
+	pub position: [ f32; 3 ],
+	pub normal: [ f32; 3 ],
+}
+
+#[cfg(feature = "glium")]
+implement_vertex!(BufferVertex, position, normal);
+
+impl WavefrontModel {
+	pub fn objects(&self) -> &[WavefrontObject] {
+		&self.objects
+	}
+
+	/// Find an object by name
+	pub fn object(&self, name: &str) -> Option<&WavefrontObject> {
+		self.objects.iter().find(|object| object.name == name)
+	}
+
+	pub fn vertices(&self) -> &[[f32; 3]] {
+		&self.vertices
+	}
+
+	/// Mutable access to vertex positions, e.g. to transform the model in place. The pool can't be
+	/// resized so face indices stay valid.
+	pub fn vertices_mut(&mut self) -> &mut [[f32; 3]] {
+		self.seams.take();
+
+		&mut self.vertices
+	}
+
+	pub fn normals(&self) -> &[[f32; 3]] {
+		&self.normals
+	}
+
+	pub fn normals_mut(&mut self) -> &mut [[f32; 3]] {
+		self.seams.take();
+
+		&mut self.normals
+	}
+
+	/// Texture vertices as `[u, v, w]`, with `w` defaulting to 0
+	pub fn texcoords(&self) -> &[[f32; 3]] {
+		&self.texcoords
+	}
+
+	pub fn texcoords_mut(&mut self) -> &mut [[f32; 3]] {
+		&mut self.texcoords
+	}
+
+	/// Weights of the vertices, from their optional fourth coordinate. Defaults to 1.
+	pub fn weights(&self) -> &[f32] {
+		&self.weights
+	}
+
+	/// Parameter vertices from `vp` as `[u, v, w]`, with `v` defaulting to 0 and `w` to 1
+	pub fn parameter_vertices(&self) -> &[[f32; 3]] {
+		&self.parameter_vertices
+	}
+
+	/// Curves in the parameter space of a surface from `curv2` statements, referenced by the
+	/// trimming loops and special curves of surfaces
+	pub fn curves_2d(&self) -> &[Curve2] {
+		&self.curves_2d
+	}
+
+	/// Connections between surfaces from `con` statements
+	pub fn connections(&self) -> &[Connection] {
+		&self.connections
+	}
+
+	/// Statements in the OBJ file and its material libraries that were ignored or skipped while
+	/// parsing
+	pub fn warnings(&self) -> &[Warning] {
+		&self.warnings
+	}
+
+	/// Find a surface by its index among all of the model's surfaces, as used by connections
+	pub fn surface(&self, index: usize) -> Option<&Surface> {
+		nth_surface(&self.objects, index)
+	}
+
+	/// Find a free-form curve by its index among all of the model's curves
+	pub fn curve(&self, index: usize) -> Option<&Curve> {
+		self.objects.iter().flat_map(|object| object.curves.iter()).nth(index)
+	}
+
+	/// Materials loaded from the file's material libraries, if it names any that could be loaded
+	pub fn materials(&self) -> Option<&mtl::WavefrontMaterials> {
+		self.materials.as_ref()
+	}
+
+	/// Look up the material a mesh uses in the material libraries
+	pub fn mesh_material(&self, mesh: &WavefrontMesh) -> Option<&mtl::WavefrontMaterial> {
+		match (self.materials.as_ref(), mesh.material_name.as_ref()) {
+			(Some(materials), Some(name)) => materials.get(name),
+			_ => None
+		}
+	}
+
+	/// Names of every group used by an element, in the order they first appear
+	pub fn group_names(&self) -> Vec<&str> {
+		let mut names: Vec<&str> = Vec::new();
+
+		for object in self.objects.iter() {
+			let groups = object.faces.iter().map(|face| face.groups())
+				.chain(object.lines.iter().map(|line| line.groups()))
+				.chain(object.points.iter().map(|point| point.groups()))
+				.chain(object.curves.iter().map(|curve| curve.groups()))
+				.chain(object.surfaces.iter().map(|surface| surface.groups()));
+
+			for name in groups.flat_map(|groups| groups.iter()) {
+				if !names.contains(&name.as_str()) {
+					names.push(name);
+				}
+			}
+		}
+
+		names
+	}
+
+	/// All faces in a group along with the object each belongs to
+	pub fn group_faces(&self, group: &str) -> Vec<(&WavefrontObject, &Face)> {
+		self.objects.iter()
+			.flat_map(|object| object.faces.iter().map(move |face| (object, face)))
+			.filter(|&(_, face)| face.in_group(group))
+			.collect()
+	}
+
+	/// Copy of the model containing only the elements in a group. Objects with nothing in the
+	/// group are dropped. Vertex pools and materials are kept whole so indices remain valid.
+	/// Connections are kept if both of their surfaces are.
+	pub fn extract_group(&self, group: &str) -> WavefrontModel {
+		// New index of each surface that is kept, by its index in this model
+		let mut surface_indices = HashMap::new();
+		let mut first_surface = 0;
+
+		let objects = self.objects.iter()
+			.map(|object| {
+				let mut extracted = WavefrontObject::new(object.name.clone());
+
+				for mesh in object.meshes.iter() {
+					for face in object.mesh_faces(mesh).iter().filter(|face| face.in_group(group)) {
+						extracted.push_face(face.clone(), &mesh.material_name);
+					}
+
+					for line in object.mesh_lines(mesh).iter().filter(|line| line.in_group(group)) {
+						extracted.push_line(line.clone(), &mesh.material_name);
+					}
+
+					for point in object.mesh_points(mesh).iter().filter(|point| point.in_group(group)) {
+						extracted.push_point(point.clone(), &mesh.material_name);
+					}
+
+					for curve in object.mesh_curves(mesh).iter().filter(|curve| curve.in_group(group)) {
+						extracted.push_curve(curve.clone(), &mesh.material_name);
+					}
+
+					for (index, surface) in mesh.surface_range().zip(object.mesh_surfaces(mesh)) {
+						if surface.in_group(group) {
+							surface_indices.insert(first_surface + index, surface_indices.len());
+							extracted.push_surface(surface.clone(), &mesh.material_name);
+						}
+					}
+				}
+
+				first_surface += object.surfaces.len();
+
+				extracted
+			})
+			.filter(|object| !object.is_empty())
+			.collect();
+
+		let connections = self.connections.iter()
+			.filter_map(|connection| {
+				let first = *surface_indices.get(&connection.first.surface)?;
+				let second = *surface_indices.get(&connection.second.surface)?;
+
+				Some(Connection {
+					first: ConnectedCurve { surface: first, ..connection.first },
+					second: ConnectedCurve { surface: second, ..connection.second },
+				})
+			})
+			.collect();
+
+		WavefrontModel {
+			materials: self.materials.clone(),
+			objects,
+			vertices: self.vertices.clone(),
+			weights: self.weights.clone(),
+			normals: self.normals.clone(),
+			texcoords: self.texcoords.clone(),
+			parameter_vertices: self.parameter_vertices.clone(),
+			curves_2d: self.curves_2d.clone(),
+			connections,
+			warnings: self.warnings.clone(),
+			seams: OnceLock::new(),
+		}
+	}
+
+	/// Generate normals for every face that doesn't have them, adding them to the normal pool.
+	/// `to_vertices` shades such faces the same way without changing the model.
+	pub fn generate_normals(&mut self) {
+		generate_normals(&mut self.objects, &self.vertices, &mut self.normals);
+	}
+
+	// Indices are checked against the pools while parsing so can't be out of range here
+	fn face_positions(&self, face: &Face) -> Vec<[f32; 3]> {
+		face.vertices.iter().map(|v| self.vertices[v.vertex as usize]).collect()
+	}
+
+	/// Approximate a free-form curve with a polyline, using the technique set by `ctech`. The curve
+	/// is given by its index among all of the model's curves, as for `curve`.
+	pub fn curve_polyline(&self, index: usize) -> Option<Vec<[f32; 3]>> {
+		let curve = self.curve(index)?;
+		let points = self.control_points(curve.control_points.iter().cloned());
+		let evaluator = CurveEvaluator::new(curve.basis_type, &curve.basis, curve.rational, points);
+
+		Some(evaluator.polyline(curve.range.0, curve.range.1, &curve.technique, &self.special_parameters(&curve.special_points)))
+	}
+
+	/// Triangulate a free-form surface into a triangle list, using the technique set by `stech`.
+	/// The surface is given by its index among all of the model's surfaces, as for `surface`.
+	/// Parts of the surface outside its trimming loops or inside its holes are left out. Along
+	/// connections to other surfaces in the model the samples are shared, so the triangles of
+	/// connected surfaces meet without cracks.
+	pub fn surface_triangles(&self, index: usize) -> Option<Vec<BufferVertex>> {
+		let surface = self.surface(index)?;
+		let evaluator = self.surface_evaluator(surface);
+		let grid = self.surface_grid(surface, &evaluator);
+
+		// The 2D curve, samples and their positions of each seam on this surface
+		let seams: Vec<_> = self.seams().iter()
+			.flat_map(|(ends, seam)| {
+				ends.iter().zip(seam.sides.iter())
+					.filter(|&(end, _)| end.surface == index)
+					.map(move |(end, samples)| (end.curve, &samples[..], &seam.positions[..]))
+			})
+			.collect();
+
+		let mut shared = SharedPositions::new();
+
+		for &(_, samples, positions) in seams.iter() {
+			for (&(_, p), &position) in samples.iter().zip(positions.iter()) {
+				shared.insert(shared_key(p), position);
+			}
+		}
+
+		let polygon = |segments: &[CurveSegment]| {
+			let spans: Vec<TrimSpan> = segments.iter()
+				.map(|segment| {
+					let curve = &self.curves_2d[segment.curve as usize];
+
+					TrimSpan {
+						curve: self.curve_2d_evaluator(curve),
+						start: segment.start,
+						end: segment.end,
+						special: self.special_parameters(&curve.special_points),
+						shared: seams.iter()
+							.filter(|&&(curve, _, _)| curve == segment.curve)
+							.map(|&(_, samples, _)| samples)
+							.collect(),
+					}
+				})
+				.collect();
+
+			trim_loop(&spans, &surface.curve_technique, &evaluator)
+		};
+
+		let ((u0, u1), (v0, v1)) = (surface.range_u, surface.range_v);
+
+		let regions: Vec<Region> = surface.trim_regions.iter()
+			.map(|region| Region {
+				outer: match region.outer {
+					Some(ref outer) => polygon(outer),
+					None => vec![ [ u0, v0 ], [ u1, v0 ], [ u1, v1 ], [ u0, v1 ] ],
+				},
+				holes: region.holes.iter().map(|hole| polygon(hole)).collect(),
+			})
+			.collect();
+
+		Some(tessellate_surface(&evaluator, &grid, &regions, &shared))
+	}
+
+	/// The samples along every connection between surfaces, with the ends of the connection they
+	/// belong to. Worked out the first time a surface is tessellated.
+	fn seams(&self) -> &[([ConnectedCurve; 2], Seam)] {
+		self.seams.get_or_init(|| {
+			self.connections.iter()
+				.filter_map(|connection| {
+					let ends = [ connection.first, connection.second ];
+
+					// Both sides are sampled the same way whichever surface is being tessellated
+					let surfaces = [ self.surface(ends[0].surface)?, self.surface(ends[1].surface)? ];
+
+					let evaluators: Vec<SurfaceEvaluator> = surfaces.iter().map(|surface| self.surface_evaluator(surface)).collect();
+					let grids: Vec<_> = surfaces.iter().zip(evaluators.iter()).map(|(surface, evaluator)| self.surface_grid(surface, evaluator)).collect();
+					let curves: Vec<&Curve2> = ends.iter().map(|end| &self.curves_2d[end.curve as usize]).collect();
+					let curve_evaluators: Vec<CurveEvaluator> = curves.iter().map(|curve| self.curve_2d_evaluator(curve)).collect();
+					let special: Vec<Vec<f32>> = curves.iter().map(|curve| self.special_parameters(&curve.special_points)).collect();
+
+					let sides: Vec<SeamSide> = (0..2)
+						.map(|k| SeamSide {
+							surface: &evaluators[k],
+							grid: &grids[k],
+							curve: &curve_evaluators[k],
+							start: ends[k].start,
+							end: ends[k].end,
+							special: &special[k],
+							technique: &surfaces[k].curve_technique,
+						})
+						.collect();
+
+					seam([ &sides[0], &sides[1] ]).map(|seam| (ends, seam))
+				})
+				.collect()
+		})
+	}
+
+	fn surface_evaluator<'a>(&self, surface: &'a Surface) -> SurfaceEvaluator<'a> {
+		let points = self.control_points(surface.control_points.iter().map(|v| v.vertex));
+
+		// Every control point uses the same index form, so either all have normals or none do
+		let normals = surface.control_points.iter()
+			.map(|v| v.normal.map(|n| self.normals[n as usize]))
+			.collect::<Option<Vec<_>>>();
+
+		SurfaceEvaluator::new(
+			surface.basis_type, (&surface.basis_u, &surface.basis_v), surface.size, surface.rational, points, normals
+		)
+	}
+
+	/// Grid lines a surface is tessellated along, from its technique and special points
+	fn surface_grid(&self, surface: &Surface, evaluator: &SurfaceEvaluator) -> (Vec<f32>, Vec<f32>) {
+		let special_points: Vec<[f32; 2]> = surface.special_points.iter()
+			.map(|&i| [ self.parameter_vertices[i as usize][0], self.parameter_vertices[i as usize][1] ])
+			.collect();
+
+		evaluator.grid(surface.range_u, surface.range_v, &surface.technique, &special_points)
+	}
+
+	fn curve_2d_evaluator<'a>(&self, curve: &'a Curve2) -> CurveEvaluator<'a> {
+		let points = curve.control_points.iter()
+			.map(|&i| {
+				let p = self.parameter_vertices[i as usize];
+
+				[ p[0], p[1], 0.0, p[2] ]
+			})
+			.collect();
+
+		CurveEvaluator::new(curve.basis_type, &curve.basis, curve.rational, points)
+	}
+
+	/// Control points as `[x, y, z, weight]`
+	fn control_points<I>(&self, indices: I) -> Vec<[f32; 4]> where I: Iterator<Item = u32> {
+		indices
+			.map(|i| {
+				let (p, w) = (self.vertices[i as usize], self.weights[i as usize]);
+
+				[ p[0], p[1], p[2], w ]
+			})
+			.collect()
+	}
+
+	/// Curve parameter values of special points, which only use the `u` coordinate
+	fn special_parameters(&self, points: &[u32]) -> Vec<f32> {
+		points.iter().map(|&i| self.parameter_vertices[i as usize][0]).collect()
+	}
+
+	/// Triangulate the model's faces and free-form surfaces into one vertex batch per object and
+	/// material, to be drawn as a triangle list. Meshes in the same object that use the same
+	/// material are merged. Faces without normals are shaded following their smoothing groups, as
+	/// `generate_normals` would.
+	pub fn to_vertices(&self) -> Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)> {
+		let smoothed = smooth_normals(&self.objects, &self.vertices);
+
+		// Meshes are visited in file order, so their surfaces are too
+		let mut surfaces = 0..;
+
+		self.batches(|object, mesh, vertices| {
+			for f in object.mesh_faces(mesh) {
+				self.triangulate_face(f, &smoothed, vertices);
+			}
+
+			for (index, _) in surfaces.by_ref().zip(mesh.surface_range()) {
+				vertices.extend(self.surface_triangles(index).unwrap_or_default());
+			}
+		})
+	}
+
+	/// Split the model's lines and free-form curves into segments, batched like `to_vertices`, to
+	/// be drawn as a line list
+	pub fn to_line_vertices(&self) -> Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)> {
+		let segment_vertex = |position| BufferVertex { position, normal: [ 0.0, 0.0, 0.0 ] };
+
+		// Meshes are visited in file order, so their curves are too
+		let mut curves = 0..;
+
+		self.batches(|object, mesh, vertices| {
+			for line in object.mesh_lines(mesh) {
+				for segment in line.vertices.windows(2) {
+					vertices.extend(segment.iter().map(|v| segment_vertex(self.vertices[v.vertex as usize])));
+				}
+			}
+
+			for (index, _) in curves.by_ref().zip(mesh.curve_range()) {
+				for segment in self.curve_polyline(index).unwrap_or_default().windows(2) {
+					vertices.extend(segment.iter().cloned().map(segment_vertex));
+				}
+			}
+		})
+	}
+
+	/// The model's points, batched like `to_vertices`, to be drawn as a point list
+	pub fn to_point_vertices(&self) -> Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)> {
+		self.batches(|object, mesh, vertices| {
+			for point in object.mesh_points(mesh) {
+				vertices.extend(point.vertices.iter().map(|&v| BufferVertex {
+					position: self.vertices[v as usize],
+					normal: [ 0.0, 0.0, 0.0 ],
+				}));
+			}
+		})
+	}
+
+	/// Collect vertices into one batch per object and material, dropping empty batches
+	fn batches<F>(&self, mut emit: F) -> Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)>
+		where F: FnMut(&WavefrontObject, &WavefrontMesh, &mut Vec<BufferVertex>)
+	{
+		let mut result = Vec::new();
+
+		for object in self.objects.iter() {
+			let mut batches: Vec<(Option<&str>, Vec<BufferVertex>)> = Vec::new();
+
+			for mesh in object.meshes.iter() {
+				let position = match batches.iter().position(|&(name, _)| name == mesh.material_name()) {
+					Some(position) => position,
+					None => {
+						batches.push((mesh.material_name(), Vec::new()));
+
+						batches.len() - 1
+					}
+				};
+
+				emit(object, mesh, &mut batches[position].1);
+			}
+
+			result.extend(batches.into_iter()
+				.filter(|(_, vertices)| !vertices.is_empty())
+				.map(|(name, vertices)| (vertices, self.resolve_material(name))));
+		}
+
+		result
+	}
+
+	fn triangulate_face(&self, f: &Face, smoothed: &HashMap<(u32, u32), [f32; 3]>, out: &mut Vec<BufferVertex>) {
+		let positions = self.face_positions(f);
+
+		// Faces without normals outside of a smoothing group are shaded flat using the normal of
+		// the polygon's plane
+		let face_normal = normalize(newell_normal(&positions));
+
+		let normals: Vec<[f32; 3]> = f.vertices.iter().map(|v| match v.normal {
+			Some(index) => self.normals[index as usize],
+			None if f.smoothing_group == 0 => face_normal,
+			None => smoothed[&(v.vertex, f.smoothing_group)],
+		}).collect();
+
+		for triangle in triangulate(&positions) {
+			out.extend(triangle.iter().map(|&i| BufferVertex { position: positions[i], normal: normals[i] }));
+		}
+	}
+
+	fn resolve_material(&self, name: Option<&str>) -> mtl::WavefrontMaterial {
+		let name = name.unwrap_or("Material");
+
+		match self.materials.as_ref().and_then(|materials| materials.get(name)) {
+			Some(material) => material.clone(),
+			None => mtl::WavefrontMaterial {
+				specular_exponent: 1.0,
+				ambient: [1.0, 0.0, 0.0],
+				diffuse: [1.0, 0.0, 0.0],
+				specular: [0.7, 0.7, 0.7],
+				..mtl::WavefrontMaterial::new(String::from("Default material"))
+			}
+		}
+	}
+}
+
+/// Smooth normals of the vertices of faces without normals, by vertex and smoothing group. Each
+/// is the area weighted average of the normals of the faces around the vertex that share the
+/// smoothing group, so edges between different groups stay hard. Faces in smoothing group 0 are
+/// shaded flat so aren't included.
+fn smooth_normals(objects: &[WavefrontObject], vertices: &[[f32; 3]]) -> HashMap<(u32, u32), [f32; 3]> {
+	let mut sums: HashMap<(u32, u32), [f32; 3]> = HashMap::new();
+
+	for face in objects.iter().flat_map(|object| object.faces.iter()) {
+		if face.has_normals() || face.smoothing_group == 0 {
+			continue;
+		}
+
+		let positions: Vec<[f32; 3]> = face.vertices.iter().map(|v| vertices[v.vertex as usize]).collect();
+		let normal = newell_normal(&positions);
+
+		for v in face.vertices.iter() {
+			let sum = sums.entry((v.vertex, face.smoothing_group)).or_insert([ 0.0, 0.0, 0.0 ]);
+
+			for axis in 0..3 {
+				sum[axis] += normal[axis];
+			}
+		}
+	}
+
+	sums.into_iter().map(|(key, sum)| (key, normalize(sum))).collect()
+}
+
+/// Generate normals for every face that doesn't have them and add them to the pool.
+///
+/// Faces in smoothing group 0 get a flat normal, the others the smooth normals from
+/// `smooth_normals`. Faces that already have normals are left untouched.
+fn generate_normals(objects: &mut [WavefrontObject], vertices: &[[f32; 3]], normals: &mut Vec<[f32; 3]>) {
+	let mut smoothed: HashMap<(u32, u32), u32> = HashMap::new();
+	let sums = smooth_normals(objects, vertices);
+
+	for object in objects.iter_mut() {
+		for face in object.faces.iter_mut() {
+			if face.has_normals() {
+				continue;
+			}
+
+			if face.smoothing_group == 0 {
+				let positions: Vec<[f32; 3]> = face.vertices.iter().map(|v| vertices[v.vertex as usize]).collect();
+				let index = normals.len() as u32;
+
+				normals.push(normalize(newell_normal(&positions)));
+
+				for v in face.vertices.iter_mut() {
+					v.normal = Some(index);
+				}
+			} else {
+				for v in face.vertices.iter_mut() {
+					let key = (v.vertex, face.smoothing_group);
+					let next = normals.len() as u32;
+					let index = *smoothed.entry(key).or_insert(next);
+
+					if index == next {
+						normals.push(sums[&key]);
+					}
+
+					v.normal = Some(index);
+				}
+			}
+		}
+	}
+}
+
+named!(negative, tag!("-"));
+
+named!(parse_face_index<i64>,
+	map_res!(
+		recognize!(pair!(opt!(negative), digit)),
+		|num: &[u8]| str::from_utf8(num).map_err(|_| ()).and_then(|num| num.parse::<i64>().map_err(|_| ()))
+	)
+);
+
+/// Convert a one-based or negative relative reference into a zero-based index, given the number
+/// of elements of that type declared so far
+fn resolve_index(index: i64, count: usize, kind: &str) -> Result<u32, String> {
+	let resolved = if index > 0 {
+		index - 1
+	} else if index < 0 {
+		count as i64 + index
+	} else {
+		return Err(format!("Invalid {} index 0, indices start at 1", kind));
+	};
+
+	if resolved < 0 {
+		return Err(format!("Relative {} index {} refers before the first {}, only {} declared so far", kind, index, kind, count));
+	}
+
+	if resolved >= count as i64 {
+		return Err(format!("Reference to {} {} but only {} declared so far", kind, index, count));
+	}
+
+	Ok(resolved as u32)
+}
+
+named!(parse_vector3<&[u8], [f32; 3]>,
+	do_parse!(
+		x: parse_float >>
+		space >>
+		y: parse_float >>
+		space >>
+		z: parse_float >>
+		line_ending >>
+		([ x, y, z ])
+	)
+);
+
+named!(vertex<&[u8], ([f32; 3], f32)>,
+	do_parse!(
+		tag!("v") >>
+		space >>
+		x: parse_float >>
+		space >>
+		y: parse_float >>
+		space >>
+		z: parse_float >>
+		w: opt!(preceded!(space, parse_float)) >>
+		opt!(space) >>
+		line_ending >>
+		(([ x, y, z ], w.unwrap_or(1.0)))
+	)
+);
+named!(normal <&[u8], [f32; 3]>, do_parse!(tag!("vn") >> space >> vector: parse_vector3 >> (vector)));
+named!(texcoord<&[u8], [f32; 3]>,
+	do_parse!(
+		tag!("vt") >>
+		space >>
+		u: parse_float >>
+		v: opt!(preceded!(space, parse_float)) >>
+		w: opt!(preceded!(space, parse_float)) >>
+		line_ending >>
+		([ u, v.unwrap_or(0.0), w.unwrap_or(0.0) ])
+	)
+);
+named!(face_vertex<&[u8], RawFaceVertex>,
+	do_parse!(
+		v: parse_face_index >>
+		rest: opt!(preceded!(
+			tag!("/"),
+			pair!(opt!(parse_face_index), opt!(preceded!(tag!("/"), parse_face_index)))
+		)) >>
+		({
+			let (vt, vn) = rest.unwrap_or((None, None));
+
+			(v, vt, vn)
+		})
+	)
+);
+
+named!(face <&[u8], Vec<RawFaceVertex>>,
+	do_parse!(
+		tag!("f") >>
+		vertices: many1!(preceded!(space, face_vertex)) >>
+		line_ending >>
+		(vertices)
+	)
+);
+
+named!(line_vertex<&[u8], (i64, Option<i64>)>,
+	do_parse!(
+		v: parse_face_index >>
+		vt: opt!(preceded!(tag!("/"), parse_face_index)) >>
+		(v, vt)
+	)
+);
+
+named!(line_element<&[u8], Vec<(i64, Option<i64>)>>,
+	do_parse!(
+		tag!("l") >>
+		vertices: many1!(preceded!(space, line_vertex)) >>
+		opt!(space) >>
+		line_ending >>
+		(vertices)
+	)
+);
+
+named!(point_element<&[u8], Vec<i64>>,
+	do_parse!(
+		tag!("p") >>
+		vertices: many1!(preceded!(space, parse_face_index)) >>
+		opt!(space) >>
+		line_ending >>
+		(vertices)
+	)
+);
+
+// `mtllib file...`, naming one or more material libraries separated by spaces
+named!(mtllib<&[u8], Vec<String>>,
+	do_parse!(
+		tag!("mtllib") >>
+		names: many1!(preceded!(space, group_name)) >>
+		line_ending >>
+		(names)
+	)
+);
+
+named!(usemtl<&[u8], String>,
+	do_parse!(
+		tag!("usemtl") >>
+		space >>
+		material: not_line_ending >>
+		line_ending >>
+		(String::from(str::from_utf8(material).unwrap_or("")))
+	)
+);
+
+named!(smoothing<&[u8], u32>,
+	do_parse!(
+		tag!("s") >>
+		space >>
+		group: alt!(
+			tag!("on") => { |_| 1 } |
+			tag!("off") => { |_| 0 } |
+			parse_unsigned
+		) >>
+		line_ending >>
+		(group)
+	)
+);
+
+named!(object_start<&[u8], String>,
+	do_parse!(
+		tag!("o") >>
+		space >>
+		name: take_until_and_consume!("\n") >>
+		(String::from(str::from_utf8(name).unwrap_or("<invalid object name>")))
+	)
+);
+
+named!(group_name<&[u8], String>,
+	map!(
+		take_while1!(|c| c != b' ' && c != b'\t' && c != b'\r' && c != b'\n'),
+		|name: &[u8]| String::from_utf8_lossy(name).into_owned()
+	)
+);
+
+named!(group<&[u8], Vec<String>>,
+	do_parse!(
+		tag!("g") >>
+		names: many0!(preceded!(space, group_name)) >>
+		opt!(space) >>
+		line_ending >>
+		(names)
+	)
+);
+
+named!(parameter_vertex<&[u8], [f32; 3]>,
+	do_parse!(
+		tag!("vp") >>
+		space >>
+		u: parse_float >>
+		v: opt!(preceded!(space, parse_float)) >>
+		w: opt!(preceded!(space, parse_float)) >>
+		opt!(space) >>
+		line_ending >>
+		([ u, v.unwrap_or(0.0), w.unwrap_or(1.0) ])
+	)
+);
+
+named!(basis_type<&[u8], BasisType>,
+	alt!(
+		tag!("bmatrix") => { |_| BasisType::BasisMatrix } |
+		tag!("bezier") => { |_| BasisType::Bezier } |
+		tag!("bspline") => { |_| BasisType::BSpline } |
+		tag!("cardinal") => { |_| BasisType::Cardinal } |
+		tag!("taylor") => { |_| BasisType::Taylor }
+	)
+);
+
+named!(curve_surface_type<&[u8], (bool, BasisType)>,
+	do_parse!(
+		tag!("cstype") >>
+		space >>
+		rational: opt!(terminated!(tag!("rat"), space)) >>
+		basis: basis_type >>
+		opt!(space) >>
+		line_ending >>
+		((rational.is_some(), basis))
+	)
+);
+
+named!(degree<&[u8], (u32, Option<u32>)>,
+	do_parse!(
+		tag!("deg") >>
+		space >>
+		u: parse_unsigned >>
+		v: opt!(preceded!(space, parse_unsigned)) >>
+		opt!(space) >>
+		line_ending >>
+		((u, v))
+	)
+);
+
+named!(parameter_direction<&[u8], Direction>,
+	alt!(
+		tag!("u") => { |_| Direction::U } |
+		tag!("v") => { |_| Direction::V }
+	)
+);
+
+named!(basis_matrix<&[u8], (Direction, Vec<f32>)>,
+	do_parse!(
+		tag!("bmat") >>
+		space >>
+		direction: parameter_direction >>
+		values: many1!(preceded!(space, parse_float)) >>
+		opt!(space) >>
+		line_ending >>
+		((direction, values))
+	)
+);
+
+named!(step<&[u8], (u32, Option<u32>)>,
+	do_parse!(
+		tag!("step") >>
+		space >>
+		u: parse_unsigned >>
+		v: opt!(preceded!(space, parse_unsigned)) >>
+		opt!(space) >>
+		line_ending >>
+		((u, v))
+	)
+);
+
+named!(curve_technique<&[u8], CurveTechnique>,
+	do_parse!(
+		tag!("ctech") >>
+		space >>
+		technique: alt!(
+			do_parse!(tag!("cparm") >> space >> resolution: parse_float >> (CurveTechnique::Parametric { resolution })) |
+			do_parse!(tag!("cspace") >> space >> max_length: parse_float >> (CurveTechnique::Spatial { max_length })) |
+			do_parse!(
+				tag!("curv") >>
+				space >>
+				max_distance: parse_float >>
+				space >>
+				max_angle: parse_float >>
+				(CurveTechnique::Curvature { max_distance, max_angle })
+			)
+		) >>
+		opt!(space) >>
+		line_ending >>
+		(technique)
+	)
+);
+
+named!(surface_technique<&[u8], SurfaceTechnique>,
+	do_parse!(
+		tag!("stech") >>
+		space >>
+		technique: alt!(
+			do_parse!(
+				tag!("cparma") >>
+				space >>
+				u_resolution: parse_float >>
+				space >>
+				v_resolution: parse_float >>
+				(SurfaceTechnique::Parametric { u_resolution, v_resolution })
+			) |
+			do_parse!(tag!("cparmb") >> space >> resolution: parse_float >> (SurfaceTechnique::ParametricRefined { resolution })) |
+			do_parse!(tag!("cspace") >> space >> max_length: parse_float >> (SurfaceTechnique::Spatial { max_length })) |
+			do_parse!(
+				tag!("curv") >>
+				space >>
+				max_distance: parse_float >>
+				space >>
+				max_angle: parse_float >>
+				(SurfaceTechnique::Curvature { max_distance, max_angle })
+			)
+		) >>
+		opt!(space) >>
+		line_ending >>
+		(technique)
+	)
+);
+
+named!(curve<&[u8], RawCurve>,
+	do_parse!(
+		tag!("curv") >>
+		space >>
+		u0: parse_float >>
+		space >>
+		u1: parse_float >>
+		vertices: many1!(preceded!(space, parse_face_index)) >>
+		opt!(space) >>
+		line_ending >>
+		((u0, u1), vertices)
+	)
+);
+
+named!(curve_2d<&[u8], Vec<i64>>,
+	do_parse!(
+		tag!("curv2") >>
+		vertices: many1!(preceded!(space, parse_face_index)) >>
+		opt!(space) >>
+		line_ending >>
+		(vertices)
+	)
+);
+
+named!(surface<&[u8], RawSurface>,
+	do_parse!(
+		tag!("surf") >>
+		space >>
+		s0: parse_float >>
+		space >>
+		s1: parse_float >>
+		space >>
+		t0: parse_float >>
+		space >>
+		t1: parse_float >>
+		vertices: many1!(preceded!(space, face_vertex)) >>
+		opt!(space) >>
+		line_ending >>
+		((s0, s1), (t0, t1), vertices)
+	)
+);
+
+named!(parameters<&[u8], (Direction, Vec<f32>)>,
+	do_parse!(
+		tag!("parm") >>
+		space >>
+		direction: parameter_direction >>
+		values: many1!(preceded!(space, parse_float)) >>
+		opt!(space) >>
+		line_ending >>
+		((direction, values))
+	)
+);
+
+named!(curve_segments<&[u8], Vec<RawCurveSegment>>,
+	many1!(
+		do_parse!(
+			space >>
+			start: parse_float >>
+			space >>
+			end: parse_float >>
+			space >>
+			curve: parse_face_index >>
+			((start, end, curve))
+		)
+	)
+);
+
+named!(trim<&[u8], Vec<RawCurveSegment>>,
+	do_parse!(tag!("trim") >> segments: curve_segments >> opt!(space) >> line_ending >> (segments))
+);
+
+named!(hole<&[u8], Vec<RawCurveSegment>>,
+	do_parse!(tag!("hole") >> segments: curve_segments >> opt!(space) >> line_ending >> (segments))
+);
+
+named!(special_curve<&[u8], Vec<RawCurveSegment>>,
+	do_parse!(tag!("scrv") >> segments: curve_segments >> opt!(space) >> line_ending >> (segments))
+);
+
+named!(special_points<&[u8], Vec<i64>>,
+	do_parse!(
+		tag!("sp") >>
+		points: many1!(preceded!(space, parse_face_index)) >>
+		opt!(space) >>
+		line_ending >>
+		(points)
+	)
+);
+
+named!(connected_curve<&[u8], RawConnectedCurve>,
+	do_parse!(
+		space >>
+		surface: parse_face_index >>
+		space >>
+		start: parse_float >>
+		space >>
+		end: parse_float >>
+		space >>
+		curve: parse_face_index >>
+		((surface, start, end, curve))
+	)
+);
+
+named!(connect<&[u8], (RawConnectedCurve, RawConnectedCurve)>,
+	do_parse!(
+		tag!("con") >>
+		first: connected_curve >>
+		second: connected_curve >>
+		opt!(space) >>
+		line_ending >>
+		((first, second))
+	)
+);
+
+named!(body_end<&[u8], ()>, do_parse!(tag!("end") >> opt!(space) >> line_ending >> (())));
+
+#[derive(Debug, Clone)]
+enum FileEntity {
+	Vertex([f32; 3], f32),
+	Normal([f32; 3]),
+	TexCoord([f32; 3]),
+	ParameterVertex([f32; 3]),
+	Face(Vec<RawFaceVertex>),
+	Line(Vec<(i64, Option<i64>)>),
+	Point(Vec<i64>),
+	Material(String),
+	Smoothing(u32),
+	Object(String),
+	Group(Vec<String>),
+	MatLib(Vec<String>),
+	CurveSurfaceType(bool, BasisType),
+	Degree(u32, Option<u32>),
+	BasisMatrix(Direction, Vec<f32>),
+	Step(u32, Option<u32>),
+	CurveTechnique(CurveTechnique),
+	SurfaceTechnique(SurfaceTechnique),
+	Curve(RawCurve),
+	Curve2(Vec<i64>),
+	Surface(RawSurface),
+	Body(BodyStatement),
+	Connect(RawConnectedCurve, RawConnectedCurve),
+	Unsupported,
+	Unknown,
+	Ignore
+}
+
+/// Statements only allowed between a `curv`, `curv2` or `surf` statement and its `end`
+#[derive(Debug, Clone)]
+enum BodyStatement {
+	Parameters(Direction, Vec<f32>),
+	Trim(Vec<RawCurveSegment>),
+	Hole(Vec<RawCurveSegment>),
+	SpecialCurve(Vec<RawCurveSegment>),
+	SpecialPoints(Vec<i64>),
+	End,
+}
+
+/// Statements from the OBJ specification that are recognised but not supported
+const UNSUPPORTED: &[&[u8]] = &[
+	b"bsp", b"bzp", b"cdc", b"cdp", b"res", b"mg", b"lod", b"bevel", b"c_interp", b"d_interp",
+	b"usemap", b"maplib", b"shadow_obj", b"trace_obj", b"call", b"csh",
+];
+
+/// Parse a statement with the parser for its keyword, so a malformed statement is reported rather
+/// than skipped. Blank lines and comments are ignored.
+fn entity(line: &[u8]) -> IResult<&[u8], FileEntity> {
+	let (keyword, input) = lines::keyword(line);
+
+	match keyword {
+		b"v" => map!(input, vertex, |(v, w)| FileEntity::Vertex(v, w)),
+		b"vn" => map!(input, normal, FileEntity::Normal),
+		b"vt" => map!(input, texcoord, FileEntity::TexCoord),
+		b"vp" => map!(input, parameter_vertex, FileEntity::ParameterVertex),
+		b"f" => map!(input, face, FileEntity::Face),
+		b"l" => map!(input, line_element, FileEntity::Line),
+		b"p" => map!(input, point_element, FileEntity::Point),
+		b"usemtl" => map!(input, usemtl, FileEntity::Material),
+		b"mtllib" => map!(input, mtllib, FileEntity::MatLib),
+		b"s" => map!(input, smoothing, FileEntity::Smoothing),
+		b"o" => map!(input, object_start, FileEntity::Object),
+		b"g" => map!(input, group, FileEntity::Group),
+		b"cstype" => map!(input, curve_surface_type, |(rational, basis)| FileEntity::CurveSurfaceType(rational, basis)),
+		b"deg" => map!(input, degree, |(u, v)| FileEntity::Degree(u, v)),
+		b"bmat" => map!(input, basis_matrix, |(direction, values)| FileEntity::BasisMatrix(direction, values)),
+		b"step" => map!(input, step, |(u, v)| FileEntity::Step(u, v)),
+		b"ctech" => map!(input, curve_technique, FileEntity::CurveTechnique),
+		b"stech" => map!(input, surface_technique, FileEntity::SurfaceTechnique),
+		b"curv" => map!(input, curve, FileEntity::Curve),
+		b"curv2" => map!(input, curve_2d, FileEntity::Curve2),
+		b"surf" => map!(input, surface, FileEntity::Surface),
+		b"parm" => map!(input, parameters, |(direction, values)| FileEntity::Body(BodyStatement::Parameters(direction, values))),
+		b"trim" => map!(input, trim, |segments| FileEntity::Body(BodyStatement::Trim(segments))),
+		b"hole" => map!(input, hole, |segments| FileEntity::Body(BodyStatement::Hole(segments))),
+		b"scrv" => map!(input, special_curve, |segments| FileEntity::Body(BodyStatement::SpecialCurve(segments))),
+		b"sp" => map!(input, special_points, |points| FileEntity::Body(BodyStatement::SpecialPoints(points))),
+		b"end" => map!(input, body_end, |_| FileEntity::Body(BodyStatement::End)),
+		b"con" => map!(input, connect, |(first, second)| FileEntity::Connect(first, second)),
+		b"" => IResult::Done(&line[line.len()..], FileEntity::Ignore),
+		_ if UNSUPPORTED.contains(&keyword) => IResult::Done(&line[line.len()..], FileEntity::Unsupported),
+		_ => IResult::Done(&line[line.len()..], FileEntity::Unknown),
+	}
+}
+
+/// A parametric direction of a free-form curve or surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+	U,
+	V,
+}
+
+impl Direction {
+	fn name(&self) -> &'static str {
+		match *self {
+			Direction::U => "u",
+			Direction::V => "v",
+		}
+	}
+}
+
+/// Free-form attributes from `cstype`, `deg`, `bmat` and `step`. These are state-setting, so stay
+/// in effect from one curve or surface to the next.
+#[derive(Debug, Clone, Default)]
+struct FreeFormState {
+	basis_type: Option<(bool, BasisType)>,
+	degree: Option<(u32, Option<u32>)>,
+	matrix_u: Option<Vec<f32>>,
+	matrix_v: Option<Vec<f32>>,
+	step: Option<(u32, Option<u32>)>,
+	curve_technique: CurveTechnique,
+	surface_technique: SurfaceTechnique,
+}
+
+impl FreeFormState {
+	/// Start the body of a curve or surface, checking that every attribute it needs has been set
+	fn start(&self, element: FreeFormElement, location: Location) -> Result<FreeFormBody, WavefrontError> {
+		let invalid = |message| WavefrontError::InvalidElement { location: location.clone(), message };
+
+		let (rational, basis_type) = self.basis_type
+			.ok_or_else(|| invalid(format!("{} needs a cstype statement before it", element.statement())))?;
+
+		let basis_u = self.basis(basis_type, &element, Direction::U).map_err(&invalid)?;
+		let basis_v = match element {
+			FreeFormElement::Surface { .. } => Some(self.basis(basis_type, &element, Direction::V).map_err(&invalid)?),
+			_ => None
+		};
+
+		Ok(FreeFormBody {
+			element,
+			location,
+			basis_type,
+			rational,
+			basis_u,
+			basis_v,
+			trim_regions: Vec::new(),
+			special_curves: Vec::new(),
+			special_points: Vec::new(),
+			curve_technique: self.curve_technique,
+			surface_technique: self.surface_technique,
+		})
+	}
+
+	/// The basis of one direction of an element, without its parameter values
+	fn basis(&self, basis_type: BasisType, element: &FreeFormElement, direction: Direction) -> Result<Basis, String> {
+		let degree = match (self.degree, direction) {
+			(None, _) => return Err(format!("{} needs a deg statement before it", element.statement())),
+			(Some((u, _)), Direction::U) => u,
+			(Some((_, Some(v))), Direction::V) => v,
+			(Some((_, None)), Direction::V) => {
+				return Err(format!("{} needs a degree in the v direction but deg only gives one", element.statement()))
+			},
+		};
+
+		// The spec says Cardinal splines are always cubic and any other degree is ignored
+		let degree = if basis_type == BasisType::Cardinal { 3 } else { degree };
+
+		if degree == 0 || degree > MAX_DEGREE {
+			return Err(format!("Degree {} in the {} direction is out of range, it must be between 1 and {}", degree, direction.name(), MAX_DEGREE));
+		}
+
+		if basis_type != BasisType::BasisMatrix {
+			return Ok(Basis { degree, matrix: None, step: None, parameters: Vec::new() });
+		}
+
+		let (matrix, step) = match direction {
+			Direction::U => (&self.matrix_u, self.step.map(|(u, _)| u)),
+			Direction::V => (&self.matrix_v, self.step.and_then(|(_, v)| v)),
+		};
+
+		let matrix = match *matrix {
+			Some(ref matrix) => matrix.clone(),
+			None => return Err(format!("bmatrix {} needs a `bmat {}` statement before it", element.statement(), direction.name())),
+		};
+
+		let size = ((degree + 1) * (degree + 1)) as usize;
+
+		if matrix.len() != size {
+			return Err(format!(
+				"Basis matrix for the {} direction has {} values but degree {} needs {}",
+				direction.name(), matrix.len(), degree, size
+			));
+		}
+
+		match step {
+			Some(0) => Err(format!("Step size for the {} direction must be at least 1", direction.name())),
+			Some(step) => Ok(Basis { degree, matrix: Some(matrix), step: Some(step), parameters: Vec::new() }),
+			None => Err(format!("bmatrix {} needs a step size for the {} direction", element.statement(), direction.name())),
+		}
+	}
+}
+
+/// The element statement that opened a free-form body, with its indices resolved
+#[derive(Debug, Clone)]
+enum FreeFormElement {
+	Curve { range: (f32, f32), control_points: Vec<u32> },
+	Curve2 { control_points: Vec<u32> },
+	Surface { range_u: (f32, f32), range_v: (f32, f32), control_points: Vec<FaceVertex> },
+}
+
+impl FreeFormElement {
+	fn statement(&self) -> &'static str {
+		match *self {
+			FreeFormElement::Curve { .. } => "curv",
+			FreeFormElement::Curve2 { .. } => "curv2",
+			FreeFormElement::Surface { .. } => "surf",
+		}
+	}
+}
+
+/// A finished free-form element
+enum FreeForm {
+	Curve(Curve),
+	Curve2(Curve2),
+	Surface(Surface),
+}
+
+/// A curve or surface whose body statements are being read
+#[derive(Debug, Clone)]
+struct FreeFormBody {
+	element: FreeFormElement,
+	location: Location,
+	basis_type: BasisType,
+	rational: bool,
+	basis_u: Basis,
+	basis_v: Option<Basis>,
+	trim_regions: Vec<TrimRegion>,
+	special_curves: Vec<Vec<CurveSegment>>,
+	special_points: Vec<u32>,
+	curve_technique: CurveTechnique,
+	surface_technique: SurfaceTechnique,
+}
+
+impl FreeFormBody {
+	/// Apply a body statement other than `end`
+	fn apply(&mut self, statement: BodyStatement, line_number: usize, line: &[u8], parameter_vertices: usize, curves_2d: usize) -> Result<(), WavefrontError> {
+		let is_surface = matches!(self.element, FreeFormElement::Surface { .. });
+
+		let surface_only = || WavefrontError::UnexpectedStatement {
+			location: Location::new(line_number, token_column(line, 0), line),
+			message: format!("`{}` is only allowed in the body of a surf, not a {}", keyword(line), self.element.statement()),
+		};
+
+		match statement {
+			// A later parm for the same direction replaces an earlier one
+			BodyStatement::Parameters(Direction::U, values) => self.basis_u.parameters = values,
+			BodyStatement::Parameters(Direction::V, values) => match self.basis_v {
+				Some(ref mut basis) => basis.parameters = values,
+				None => return Err(WavefrontError::UnexpectedStatement {
+					location: Location::new(line_number, token_column(line, 1), line),
+					message: format!("A {} only has a u direction, `parm v` is only allowed for surfaces", self.element.statement()),
+				}),
+			},
+			BodyStatement::Trim(raw) => {
+				if !is_surface {
+					return Err(surface_only());
+				}
+
+				let outer = resolve_segments(&raw, curves_2d, line_number, line)?;
+
+				self.trim_regions.push(TrimRegion { outer: Some(outer), holes: Vec::new() });
+			},
+			BodyStatement::Hole(raw) => {
+				if !is_surface {
+					return Err(surface_only());
+				}
+
+				let hole = resolve_segments(&raw, curves_2d, line_number, line)?;
+
+				// Holes cut the region started by the last trim, or the whole parameter range
+				// if there wasn't one
+				if self.trim_regions.is_empty() {
+					self.trim_regions.push(TrimRegion { outer: None, holes: Vec::new() });
+				}
+
+				self.trim_regions.last_mut().unwrap().holes.push(hole);
+			},
+			BodyStatement::SpecialCurve(raw) => {
+				if !is_surface {
+					return Err(surface_only());
+				}
+
+				let curve = resolve_segments(&raw, curves_2d, line_number, line)?;
+
+				self.special_curves.push(curve);
+			},
+			BodyStatement::SpecialPoints(raw) => {
+				for (position, &vp) in raw.iter().enumerate() {
+					self.special_points.push(resolve_at(vp, parameter_vertices, "parameter vertex", line_number, line, position)?);
+				}
+			},
+			BodyStatement::End => (),
+		}
+
+		Ok(())
+	}
+
+	/// Check the element is consistent once its `end` is reached, following the error checks
+	/// and parameter vector equations in the spec
+	fn finish(self, groups: &Arc<[String]>) -> Result<FreeForm, WavefrontError> {
+		let location = self.location;
+		let invalid = |message| WavefrontError::InvalidElement { location: location.clone(), message };
+
+		let statement = self.element.statement();
+		let basis_type = self.basis_type;
+
+		match self.element {
+			FreeFormElement::Curve { range, control_points } => {
+				check_control_points(basis_type, statement, &self.basis_u, control_points.len()).map_err(invalid)?;
+
+				Ok(FreeForm::Curve(Curve {
+					basis_type,
+					rational: self.rational,
+					basis: self.basis_u,
+					range,
+					control_points,
+					special_points: self.special_points,
+					technique: self.curve_technique,
+					groups: groups.clone(),
+				}))
+			},
+			FreeFormElement::Curve2 { control_points } => {
+				check_control_points(basis_type, statement, &self.basis_u, control_points.len()).map_err(invalid)?;
+
+				Ok(FreeForm::Curve2(Curve2 {
+					basis_type,
+					rational: self.rational,
+					basis: self.basis_u,
+					control_points,
+					special_points: self.special_points,
+				}))
+			},
+			FreeFormElement::Surface { range_u, range_v, control_points } => {
+				let basis_v = self.basis_v.expect("surfaces always have a v basis");
+
+				let columns = required_control_points(basis_type, &self.basis_u, Direction::U).map_err(&invalid)?;
+				let rows = required_control_points(basis_type, &basis_v, Direction::V).map_err(&invalid)?;
+
+				if columns * rows != control_points.len() {
+					return Err(invalid(format!(
+						"{} surface with {} u and {} v parameter values needs {} x {} control points but has {}",
+						basis_type.name(), self.basis_u.parameters.len(), basis_v.parameters.len(), columns, rows, control_points.len()
+					)));
+				}
+
+				let form = |v: &FaceVertex| (v.texcoord.is_some(), v.normal.is_some());
+				let first = form(&control_points[0]);
+
+				if let Some(position) = control_points.iter().position(|v| form(v) != first) {
+					return Err(invalid(format!(
+						"Surface mixes vertex index forms: vertex 1 is {} but vertex {} is {}",
+						describe_form(first), position + 1, describe_form(form(&control_points[position]))
+					)));
+				}
+
+				Ok(FreeForm::Surface(Surface {
+					basis_type,
+					rational: self.rational,
+					basis_u: self.basis_u,
+					basis_v,
+					range_u,
+					range_v,
+					size: (columns, rows),
+					control_points,
+					trim_regions: self.trim_regions,
+					special_curves: self.special_curves,
+					special_points: self.special_points,
+					technique: self.surface_technique,
+					curve_technique: self.curve_technique,
+					groups: groups.clone(),
+				}))
+			},
+		}
+	}
+}
+
+/// Check a curve has the number of control points its type, degree and parameter values need
+fn check_control_points(basis_type: BasisType, statement: &str, basis: &Basis, count: usize) -> Result<(), String> {
+	let required = required_control_points(basis_type, basis, Direction::U)?;
+
+	if required != count {
+		return Err(format!(
+			"{} {} of degree {} with {} parameter values needs {} control points but has {}",
+			basis_type.name(), statement, basis.degree, basis.parameters.len(), required, count
+		));
+	}
+
+	Ok(())
+}
+
+/// Number of control points needed in one direction, given the number of global parameter
+/// values or knots
+fn required_control_points(basis_type: BasisType, basis: &Basis, direction: Direction) -> Result<usize, String> {
+	let count = basis.parameters.len();
+	let degree = basis.degree as usize;
+
+	if count == 0 {
+		return Err(format!("Missing `parm {}` statement", direction.name()));
+	}
+
+	if count < 2 {
+		return Err(format!("parm {} has 1 value, at least 2 are required", direction.name()));
+	}
+
+	if let Some(position) = basis.parameters.windows(2).position(|pair| pair[1] < pair[0]) {
+		return Err(format!(
+			"Parameter values in the {} direction must increase monotonically but value {} ({}) is less than value {} ({})",
+			direction.name(), position + 2, basis.parameters[position + 1], position + 1, basis.parameters[position]
+		));
+	}
+
+	match basis_type {
+		BasisType::Bezier => Ok((count - 1) * degree + 1),
+		BasisType::BSpline => {
+			if count < 2 * (degree + 1) {
+				return Err(format!(
+					"bspline of degree {} needs at least {} knots in the {} direction but has {}",
+					degree, 2 * (degree + 1), direction.name(), count
+				));
+			}
+
+			Ok(count - degree - 1)
+		},
+		BasisType::Cardinal => Ok(count + 2),
+		BasisType::Taylor => Ok((count - 1) * (degree + 1)),
+		BasisType::BasisMatrix => Ok((count - 2) * basis.step.unwrap_or(1) as usize + degree + 1),
+	}
+}
+
+/// Resolve the `u0 u1 curv2d` triples of a `trim`, `hole` or `scrv` statement
+fn resolve_segments(raw: &[RawCurveSegment], curves_2d: usize, line_number: usize, line: &[u8]) -> Result<Vec<CurveSegment>, WavefrontError> {
+	raw.iter().enumerate()
+		.map(|(i, &(start, end, curve))| Ok(CurveSegment {
+			start,
+			end,
+			curve: resolve_at(curve, curves_2d, "2D curve", line_number, line, i * 3 + 2)?,
+		}))
+		.collect()
+}
+
+/// The surface at an index among the surfaces of all objects, in file order
+fn nth_surface(objects: &[WavefrontObject], index: usize) -> Option<&Surface> {
+	objects.iter().flat_map(|object| object.surfaces.iter()).nth(index)
+}
+
+/// The keyword a statement starts with, for error messages
+fn keyword(line: &[u8]) -> String {
+	String::from_utf8_lossy(lines::keyword(line).0).into_owned()
+}
+
+/// How to name the object created for geometry that appears before any `o` statement
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultObjectName {
+	/// Always use the given name
+	Fixed(String),
+	/// Use the first name of the group in effect when the object is created
+	Group,
+	/// Use the file name without its extension. Only applies to `load_with_options`; when parsing
+	/// from memory this behaves like `Fixed("default")`.
+	FileStem,
+}
+
+/// Options controlling how OBJ files are parsed
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+	pub default_object_name: DefaultObjectName,
+	/// Whether to stop at malformed and unknown statements or skip them with a warning. Also
+	/// applies to the material libraries when loading.
+	pub mode: ParseMode,
+	/// Light `spectral` colours in the material libraries are viewed under when loading
+	pub illuminant: Illuminant,
+}
+
+impl Default for ParseOptions {
+	fn default() -> ParseOptions {
+		ParseOptions {
+			default_object_name: DefaultObjectName::Fixed(String::from("default")),
+			mode: ParseMode::default(),
+			illuminant: Illuminant::default(),
+		}
+	}
+}
+
+/// Parse the contents of an OBJ file, returning the model data and the names of the material
+/// libraries it references, in the order they first appear. The pools hold the file's elements as
+/// written: faces without normals don't get any, see `WavefrontModelData::generate_normals`.
+///
+/// Stops at the first malformed or unknown statement. Use `parse_with_options` with
+/// `ParseMode::Lenient` to skip them with a warning instead.
+pub fn parse(input: &[u8]) -> Result<(WavefrontModelData, Vec<String>), WavefrontError> {
+	parse_with_options(input, &ParseOptions::default())
+}
+
+pub fn parse_with_options(input: &[u8], options: &ParseOptions) -> Result<(WavefrontModelData, Vec<String>), WavefrontError> {
+	parse_located(input, options).map(|(model, mtl_libs)| (model, library_names(mtl_libs)))
+}
+
+/// Like `parse`, but also returns where each material library is named for error reporting
+fn parse_located(input: &[u8], options: &ParseOptions) -> Result<(WavefrontModelData, Vec<(String, Location)>), WavefrontError> {
+	let mut builder = ModelBuilder::new(options);
+
+	let mtl_libs = parse_stream(input, &mut builder, options)?;
+
+	Ok((builder.model, mtl_libs))
+}
+
+fn library_names(mtl_libs: Vec<(String, Location)>) -> Vec<String> {
+	mtl_libs.into_iter().map(|(name, _)| name).collect()
+}
+
+/// Receives the elements of an OBJ file as `parse_reader` parses them, so callers can build their
+/// own structures without the whole file or a `WavefrontModel` in memory.
+///
+/// Elements are passed in file order with their indices resolved and zero-based, counting the
+/// vertices, normals and so on passed before them. Every method does nothing by default.
+pub trait Visitor {
+	/// A `v` statement: the position and its weight, which defaults to 1
+	fn vertex(&mut self, _position: [f32; 3], _weight: f32) {}
+
+	/// A `vn` statement
+	fn normal(&mut self, _normal: [f32; 3]) {}
+
+	/// A `vt` statement, with missing coordinates set to 0
+	fn texcoord(&mut self, _texcoord: [f32; 3]) {}
+
+	/// A `vp` statement
+	fn parameter_vertex(&mut self, _vertex: [f32; 3]) {}
+
+	fn face(&mut self, _face: Face) {}
+
+	fn line(&mut self, _line: Line) {}
+
+	fn point(&mut self, _point: Point) {}
+
+	/// A free-form curve, once its body has been read
+	fn curve(&mut self, _curve: Curve) {}
+
+	/// A 2D curve, once its body has been read
+	fn curve_2d(&mut self, _curve: Curve2) {}
+
+	/// A free-form surface, once its body has been read
+	fn surface(&mut self, _surface: Surface) {}
+
+	fn connection(&mut self, _connection: Connection) {}
+
+	/// An `o` statement
+	fn object(&mut self, _name: String) {}
+
+	/// A `g` statement, with `default` for a statement without names. Elements also carry the
+	/// groups they are in.
+	fn group(&mut self, _names: &[String]) {}
+
+	/// An `s` statement. Faces also carry their smoothing group.
+	fn smoothing_group(&mut self, _group: u32) {}
+
+	/// A `usemtl` statement, applying to the elements after it
+	fn material(&mut self, _name: String) {}
+
+	/// A material library named by an `mtllib` statement, called once for each name
+	fn material_library(&mut self, _name: String) {}
+
+	/// A statement that was ignored, or skipped in lenient mode
+	fn warning(&mut self, _warning: Warning) {}
+}
+
+/// Parse an OBJ file from a reader, passing its elements to a visitor as they are read. Only a
+/// block of lines is held in memory at a time, so files of any size can be streamed.
+pub fn parse_reader<R: BufRead, V: Visitor>(reader: R, visitor: &mut V, options: &ParseOptions) -> Result<(), WavefrontError> {
+	parse_stream(reader, visitor, options).map(|_| ())
+}
+
+/// Like `parse_reader`, but also returns the material libraries named and their locations
+fn parse_stream<R: BufRead, V: Visitor>(mut reader: R, visitor: &mut V, options: &ParseOptions) -> Result<Vec<(String, Location)>, WavefrontError> {
+	let mut state = ParseState::new(options);
+	let mut block = Vec::with_capacity(BLOCK_SIZE);
+	let mut line_number = 1;
+
+	loop {
+		let lines_read = lines::read_block(&mut reader, &mut block, BLOCK_SIZE)
+			.map_err(|error| WavefrontError::Read { error })?;
+
+		if lines_read == 0 {
+			break;
+		}
+
+		state.parse_lines(&mut Lines::starting_at(&block, line_number), visitor)?;
+
+		line_number += lines_read;
+	}
+
+	state.finish(visitor)
+}
+
+/// Size of the blocks of whole statements read from a stream at a time
+const BLOCK_SIZE: usize = 1 << 16;
+
+/// Parse the contents of an OBJ file on several threads, with the same results as `parse`.
+///
+/// The input is split at line boundaries into a chunk per thread, and the statements of each
+/// chunk are parsed on their own thread. A quick pass over the state-setting statements in file
+/// order then finds the state each chunk starts in: the counts relative indices are resolved
+/// against, and the groups, smoothing group, material and free-form attributes in effect. Each
+/// thread resolves and builds the elements of its chunk from there, and the parts are joined in
+/// file order, so elements, warnings and errors are exactly those of a sequential parse.
+///
+/// `std::thread::available_parallelism` is a good choice for the number of threads. With one
+/// thread, or zero, this is the same as `parse_with_options`.
+pub fn parse_parallel(input: &[u8], options: &ParseOptions, threads: usize) -> Result<(WavefrontModelData, Vec<String>), WavefrontError> {
+	parse_parallel_located(input, options, threads).map(|(model, mtl_libs)| (model, library_names(mtl_libs)))
+}
+
+fn parse_parallel_located(input: &[u8], options: &ParseOptions, threads: usize) -> Result<(WavefrontModelData, Vec<(String, Location)>), WavefrontError> {
+	if threads <= 1 {
+		return parse_located(input, options);
+	}
+
+	let chunks: Vec<ParsedChunk> = thread::scope(|scope| {
+		let parsers: Vec<_> = lines::split(input, threads).into_iter()
+			.map(|(line_number, chunk)| scope.spawn(move || parse_chunk(chunk, line_number)))
+			.collect();
+
+		parsers.into_iter().map(|parser| parser.join().unwrap_or_else(|panic| panic::resume_unwind(panic))).collect()
+	});
+
+	// Faces, lines and points never change the state, so only the other statements are applied to
+	// find where each chunk starts. Statements that fail are reported when their chunk is built,
+	// but a vertex statement still takes up its place when skipped.
+	let mut state = ParseState::new(options);
+	let mut material = MaterialTracker::default();
+	let mut starts = Vec::with_capacity(chunks.len());
+
+	for (_, statements) in chunks.iter() {
+		starts.push((state.clone(), material.material_name.clone()));
+
+		for (line_number, line, entity) in statements {
+			match entity {
+				Ok(FileEntity::Face(_)) | Ok(FileEntity::Line(_)) | Ok(FileEntity::Point(_)) => (),
+				Ok(entity) => {
+					if state.statement(&mut material, entity.clone(), *line_number, line).is_err() {
+						state.placeholder(&mut material, line);
+					}
+				},
+				Err(_) => state.placeholder(&mut material, line),
+			}
+		}
+	}
+
+	let mut builder = ModelBuilder::new(options);
+
+	thread::scope(|scope| {
+		let builders: Vec<_> = chunks.into_iter().zip(starts)
+			.map(|((lines, statements), (mut state, material_name))| scope.spawn(move || {
+				let mut chunk = ModelBuilder::new(options);
+
+				chunk.material_name = material_name;
+
+				let result = statements.into_iter()
+					.try_for_each(|(line_number, line, entity)| state.handle(&mut chunk, &lines, line_number, &line, entity));
+
+				(chunk, result)
+			}))
+			.collect();
+
+		for chunk in builders {
+			let (chunk, result) = chunk.join().unwrap_or_else(|panic| panic::resume_unwind(panic));
+
+			result?;
+			builder.append(chunk);
+		}
+
+		Ok(())
+	})?;
+
+	let mtl_libs = state.finish(&mut builder)?;
+
+	Ok((builder.model, mtl_libs))
+}
+
+/// The statements of part of an OBJ file, parsed without applying them, and the lines they were
+/// read from for locating errors
+type ParsedChunk<'a> = (Lines<'a>, Vec<(usize, Cow<'a, [u8]>, Result<FileEntity, WavefrontError>)>);
+
+fn parse_chunk<'a>(chunk: &'a [u8], line_number: usize) -> ParsedChunk<'a> {
+	let mut lines = Lines::starting_at(chunk, line_number);
+	let mut statements = Vec::new();
+
+	for (line_number, line) in lines.by_ref() {
+		let entity = parse_line(entity, line_number, &line);
+
+		// Blank lines and comments have no effect, so aren't worth keeping
+		if let Ok(FileEntity::Ignore) = entity {
+			continue;
+		}
+
+		statements.push((line_number, line, entity));
+	}
+
+	(lines, statements)
+}
+
+/// Visitor keeping track of the material in effect and nothing else
+#[derive(Default)]
+struct MaterialTracker {
+	material_name: Option<String>,
+}
+
+impl Visitor for MaterialTracker {
+	fn material(&mut self, name: String) {
+		self.material_name = Some(name);
+	}
+}
+
+/// Visitor collecting every element into a model
+struct ModelBuilder<'a> {
+	options: &'a ParseOptions,
+	material_name: Option<String>,
+	/// Number of `o` statements seen, so an object created implicitly can be told apart
+	named_objects: usize,
+	model: WavefrontModelData,
+}
+
+impl<'a> ModelBuilder<'a> {
+	fn new(options: &'a ParseOptions) -> ModelBuilder<'a> {
+		ModelBuilder {
+			options,
+			material_name: None,
+			named_objects: 0,
+			model: WavefrontModelData::default(),
+		}
+	}
+
+	/// Add the elements another builder collected from the next part of the file. Elements before
+	/// the first `o` statement of that part continue the last object, if there is one.
+	fn append(&mut self, other: ModelBuilder) {
+		let model = other.model;
+		let continues = model.objects.len() > other.named_objects && !self.model.objects.is_empty();
+		let mut objects = model.objects.into_iter();
+
+		if continues {
+			if let (Some(last), Some(object)) = (self.model.objects.last_mut(), objects.next()) {
+				last.append(object);
+			}
+		}
+
+		self.model.objects.extend(objects);
+		self.model.vertices.extend(model.vertices);
+		self.model.weights.extend(model.weights);
+		self.model.normals.extend(model.normals);
+		self.model.texcoords.extend(model.texcoords);
+		self.model.parameter_vertices.extend(model.parameter_vertices);
+		self.model.curves_2d.extend(model.curves_2d);
+		self.model.connections.extend(model.connections);
+		self.model.warnings.extend(model.warnings);
+		self.named_objects += other.named_objects;
+	}
+}
+
+impl<'a> Visitor for ModelBuilder<'a> {
+	fn vertex(&mut self, position: [f32; 3], weight: f32) {
+		self.model.vertices.push(position);
+		self.model.weights.push(weight);
+	}
+
+	fn normal(&mut self, normal: [f32; 3]) {
+		self.model.normals.push(normal);
+	}
+
+	fn texcoord(&mut self, texcoord: [f32; 3]) {
+		self.model.texcoords.push(texcoord);
+	}
+
+	fn parameter_vertex(&mut self, vertex: [f32; 3]) {
+		self.model.parameter_vertices.push(vertex);
+	}
+
+	fn face(&mut self, face: Face) {
+		current_object(&mut self.model.objects, self.options, &face.groups).push_face(face, &self.material_name);
+	}
+
+	fn line(&mut self, line: Line) {
+		current_object(&mut self.model.objects, self.options, &line.groups).push_line(line, &self.material_name);
+	}
+
+	fn point(&mut self, point: Point) {
+		current_object(&mut self.model.objects, self.options, &point.groups).push_point(point, &self.material_name);
+	}
+
+	fn curve(&mut self, curve: Curve) {
+		current_object(&mut self.model.objects, self.options, &curve.groups).push_curve(curve, &self.material_name);
+	}
+
+	fn curve_2d(&mut self, curve: Curve2) {
+		self.model.curves_2d.push(curve);
+	}
+
+	fn surface(&mut self, surface: Surface) {
+		current_object(&mut self.model.objects, self.options, &surface.groups).push_surface(surface, &self.material_name);
+	}
+
+	fn connection(&mut self, connection: Connection) {
+		self.model.connections.push(connection);
+	}
+
+	fn object(&mut self, name: String) {
+		self.named_objects += 1;
+		self.model.objects.push(WavefrontObject::new(name));
+	}
+
+	fn material(&mut self, name: String) {
+		self.material_name = Some(name);
+	}
+
+	fn warning(&mut self, warning: Warning) {
+		self.model.warnings.push(warning);
+	}
+}
+
+/// The state-setting statements in effect while parsing an OBJ file, and counts of the elements
+/// read so far for resolving indices
+#[derive(Clone)]
+struct ParseState<'a> {
+	options: &'a ParseOptions,
+	vertices: usize,
+	normals: usize,
+	texcoords: usize,
+	parameter_vertices: usize,
+	curves_2d: usize,
+	/// Trimming regions of each surface, for checking `con` statements
+	trim_regions: Vec<Vec<TrimRegion>>,
+	/// Material libraries named so far and where, without repeats
+	mtl_libs: Vec<(String, Location)>,
+	groups: Arc<[String]>,
+	smoothing_group: u32,
+	free_form: FreeFormState,
+	body: Option<FreeFormBody>,
+}
+
+impl<'a> ParseState<'a> {
+	fn new(options: &'a ParseOptions) -> ParseState<'a> {
+		ParseState {
+			options,
+			vertices: 0,
+			normals: 0,
+			texcoords: 0,
+			parameter_vertices: 0,
+			curves_2d: 0,
+			trim_regions: Vec::new(),
+			mtl_libs: Vec::new(),
+			groups: Arc::from(vec![ String::from("default") ]),
+			smoothing_group: 0,
+			free_form: FreeFormState::default(),
+			body: None,
+		}
+	}
+
+	/// Parse each statement in a block of lines, passing elements and warnings to the visitor
+	fn parse_lines<V: Visitor>(&mut self, lines: &mut Lines, visitor: &mut V) -> Result<(), WavefrontError> {
+		while let Some((line_number, line)) = lines.next() {
+			let entity = parse_line(entity, line_number, &line);
+
+			self.handle(visitor, lines, line_number, &line, entity)?;
+		}
+
+		Ok(())
+	}
+
+	/// Apply a parsed statement, or handle the error parsing it. Errors and warnings are located
+	/// using the lines the statement came from.
+	fn handle<V: Visitor>(&mut self, visitor: &mut V, lines: &Lines, line_number: usize, line: &[u8], entity: Result<FileEntity, WavefrontError>) -> Result<(), WavefrontError> {
+		let warning = match entity.and_then(|entity| self.statement(visitor, entity, line_number, line)) {
+			Ok(warning) => warning,
+			Err(error) => {
+				let warning = self.skip(error).map_err(|error| lines.locate(error))?;
+
+				// Later statements refer to vertices by their position, so a skipped one still
+				// takes up its place
+				self.placeholder(visitor, line);
+
+				Some(warning)
+			},
+		};
+
+		if let Some(warning) = warning {
+			visitor.warning(lines.locate_warning(warning));
+		}
+
+		Ok(())
+	}
+
+	/// Apply a statement, passing any element it completes to the visitor. Returns a warning for a
+	/// statement that is recognised but not supported.
+	fn statement<V: Visitor>(&mut self, visitor: &mut V, entity: FileEntity, line_number: usize, line: &[u8]) -> Result<Option<Warning>, WavefrontError> {
+		// Only body statements may appear between a free-form element and its end
+		if let Some(ref open) = self.body {
+			match entity {
+				FileEntity::Body(_) | FileEntity::Unsupported | FileEntity::Unknown | FileEntity::Ignore => (),
+				_ => return Err(WavefrontError::UnexpectedStatement {
+					location: Location::new(line_number, token_column(line, 0), line),
+					message: format!(
+						"`{}` is not allowed in the body of the {} on line {}, expected parm, trim, hole, scrv, sp or end",
+						keyword(line), open.element.statement(), open.location.line
+					),
+				}),
+			}
+		}
+
+		match entity {
+			FileEntity::Vertex(v, w) => {
+				self.vertices += 1;
+				visitor.vertex(v, w);
+			},
+			FileEntity::Normal(n) => {
+				self.normals += 1;
+				visitor.normal(n);
+			},
+			FileEntity::TexCoord(t) => {
+				self.texcoords += 1;
+				visitor.texcoord(t);
+			},
+			FileEntity::ParameterVertex(vp) => {
+				self.parameter_vertices += 1;
+				visitor.parameter_vertex(vp);
+			},
+			FileEntity::Object(name) => visitor.object(name),
+			FileEntity::Group(names) => {
+				self.groups = if names.is_empty() {
+					Arc::from(vec![ String::from("default") ])
+				} else {
+					Arc::from(names)
+				};
+
+				visitor.group(&self.groups);
+			},
+			FileEntity::MatLib(filenames) => {
+				for (position, filename) in filenames.into_iter().enumerate() {
+					if !self.mtl_libs.iter().any(|(name, _)| *name == filename) {
+						let location = Location::new(line_number, token_column(line, position + 1), line);
+
+						self.mtl_libs.push((filename.clone(), location));
+					}
+
+					visitor.material_library(filename);
+				}
+			},
+			FileEntity::Face(raw) => {
+				let mut face = Face {
+					vertices: Vec::with_capacity(raw.len()),
+					groups: self.groups.clone(),
+					smoothing_group: self.smoothing_group,
+				};
+
+				for (position, &(v, vt, vn)) in raw.iter().enumerate() {
+					let resolve = |index, count, kind| resolve_at(index, count, kind, line_number, line, position);
+
+					face.vertices.push(FaceVertex {
+						vertex: resolve(v, self.vertices, "vertex")?,
+						texcoord: match vt {
+							Some(vt) => Some(resolve(vt, self.texcoords, "texture vertex")?),
+							None => None
+						},
+						normal: match vn {
+							Some(vn) => Some(resolve(vn, self.normals, "normal")?),
+							None => None
+						},
+					});
+				}
+
+				face.validate().map_err(|(position, message)| invalid_element_at(line_number, line, position, message))?;
+
+				visitor.face(face);
+			},
+			FileEntity::Line(raw) => {
+				let mut element = Line {
+					vertices: Vec::with_capacity(raw.len()),
+					groups: self.groups.clone(),
+				};
+
+				for (position, &(v, vt)) in raw.iter().enumerate() {
+					let resolve = |index, count, kind| resolve_at(index, count, kind, line_number, line, position);
+
+					element.vertices.push(LineVertex {
+						vertex: resolve(v, self.vertices, "vertex")?,
+						texcoord: match vt {
+							Some(vt) => Some(resolve(vt, self.texcoords, "texture vertex")?),
+							None => None
+						},
+					});
+				}
+
+				element.validate().map_err(|(position, message)| invalid_element_at(line_number, line, position, message))?;
+
+				visitor.line(element);
+			},
+			FileEntity::Point(raw) => {
+				let mut point = Point {
+					vertices: Vec::with_capacity(raw.len()),
+					groups: self.groups.clone(),
+				};
+
+				for (position, &v) in raw.iter().enumerate() {
+					point.vertices.push(resolve_at(v, self.vertices, "vertex", line_number, line, position)?);
+				}
+
+				visitor.point(point);
+			},
+			FileEntity::Material(name) => visitor.material(name),
+			FileEntity::Smoothing(group) => {
+				self.smoothing_group = group;
+				visitor.smoothing_group(group);
+			},
+			FileEntity::CurveSurfaceType(rational, basis_type) => self.free_form.basis_type = Some((rational, basis_type)),
+			FileEntity::Degree(u, v) => self.free_form.degree = Some((u, v)),
+			FileEntity::BasisMatrix(Direction::U, matrix) => self.free_form.matrix_u = Some(matrix),
+			FileEntity::BasisMatrix(Direction::V, matrix) => self.free_form.matrix_v = Some(matrix),
+			FileEntity::Step(u, v) => self.free_form.step = Some((u, v)),
+			FileEntity::CurveTechnique(technique) => {
+				technique.validate().map_err(|message| invalid_element(line_number, line, message))?;
+
+				self.free_form.curve_technique = technique;
+			},
+			FileEntity::SurfaceTechnique(technique) => {
+				technique.validate().map_err(|message| invalid_element(line_number, line, message))?;
+
+				self.free_form.surface_technique = technique;
+			},
+			FileEntity::Curve((range, raw)) => {
+				let mut control_points = Vec::with_capacity(raw.len());
+
+				// Control points follow the two parameter range values
+				for (position, &v) in raw.iter().enumerate() {
+					control_points.push(resolve_at(v, self.vertices, "vertex", line_number, line, position + 2)?);
+				}
+
+				let element = FreeFormElement::Curve { range, control_points };
+
+				self.body = Some(self.free_form.start(element, Location::new(line_number, token_column(line, 0), line))?);
+			},
+			FileEntity::Curve2(raw) => {
+				let mut control_points = Vec::with_capacity(raw.len());
+
+				for (position, &vp) in raw.iter().enumerate() {
+					control_points.push(resolve_at(vp, self.parameter_vertices, "parameter vertex", line_number, line, position)?);
+				}
+
+				let element = FreeFormElement::Curve2 { control_points };
+
+				self.body = Some(self.free_form.start(element, Location::new(line_number, token_column(line, 0), line))?);
+			},
+			FileEntity::Surface((range_u, range_v, raw)) => {
+				let mut control_points = Vec::with_capacity(raw.len());
+
+				// Control points follow the four parameter range values
+				for (position, &(v, vt, vn)) in raw.iter().enumerate() {
+					let resolve = |index, count, kind| resolve_at(index, count, kind, line_number, line, position + 4);
+
+					control_points.push(FaceVertex {
+						vertex: resolve(v, self.vertices, "vertex")?,
+						texcoord: match vt {
+							Some(vt) => Some(resolve(vt, self.texcoords, "texture vertex")?),
+							None => None
+						},
+						normal: match vn {
+							Some(vn) => Some(resolve(vn, self.normals, "normal")?),
+							None => None
+						},
+					});
+				}
+
+				let element = FreeFormElement::Surface { range_u, range_v, control_points };
+
+				self.body = Some(self.free_form.start(element, Location::new(line_number, token_column(line, 0), line))?);
+			},
+			FileEntity::Body(BodyStatement::End) => match self.body.take() {
+				Some(open) => match open.finish(&self.groups)? {
+					FreeForm::Curve(curve) => visitor.curve(curve),
+					FreeForm::Curve2(curve) => {
+						self.curves_2d += 1;
+						visitor.curve_2d(curve);
+					},
+					FreeForm::Surface(surface) => {
+						self.trim_regions.push(surface.trim_regions.clone());
+						visitor.surface(surface);
+					},
+				},
+				None => return Err(outside_body(line_number, line)),
+			},
+			FileEntity::Body(statement) => match self.body {
+				Some(ref mut open) => open.apply(statement, line_number, line, self.parameter_vertices, self.curves_2d)?,
+				None => return Err(outside_body(line_number, line)),
+			},
+			FileEntity::Connect(first, second) => {
+				let mut sides = Vec::with_capacity(2);
+
+				// Each side is four values, the surface index first and the curve index last
+				for (position, &(surface, start, end, curve)) in [ first, second ].iter().enumerate() {
+					let surface = resolve_at(surface, self.trim_regions.len(), "surface", line_number, line, position * 4)? as usize;
+					let curve = resolve_at(curve, self.curves_2d, "2D curve", line_number, line, position * 4 + 3)?;
+
+					let trimmed_by = self.trim_regions[surface].iter().any(|region| region.uses_curve(curve));
+
+					if !trimmed_by {
+						return Err(WavefrontError::InvalidElement {
+							location: Location::new(line_number, token_column(line, position * 4 + 4), line),
+							message: format!("2D curve {} is not part of a trimming loop or hole of surface {}", curve + 1, surface + 1),
+						});
+					}
+
+					sides.push(ConnectedCurve { surface, start, end, curve });
+				}
+
+				visitor.connection(Connection { first: sides[0], second: sides[1] });
+			},
+			FileEntity::Unsupported => return Ok(Some(Warning {
+				location: Location::new(line_number, token_column(line, 0), line),
+				message: format!("`{}` statements are not supported and were ignored", keyword(line)),
+			})),
+			FileEntity::Unknown => return Err(WavefrontError::UnknownStatement {
+				location: Location::new(line_number, token_column(line, 0), line),
+				message: format!("unknown statement `{}`", keyword(line)),
+			}),
+			FileEntity::Ignore => (),
+		}
+
+		Ok(None)
+	}
+
+	/// Stand in for a skipped `v`, `vn`, `vt` or `vp` statement with a vertex at the origin, so the
+	/// indices of the vertices after it don't change
+	fn placeholder<V: Visitor>(&mut self, visitor: &mut V, line: &[u8]) {
+		match lines::keyword(line).0 {
+			b"v" => {
+				self.vertices += 1;
+				visitor.vertex([ 0.0, 0.0, 0.0 ], 1.0);
+			},
+			b"vn" => {
+				self.normals += 1;
+				visitor.normal([ 0.0, 0.0, 0.0 ]);
+			},
+			b"vt" => {
+				self.texcoords += 1;
+				visitor.texcoord([ 0.0, 0.0, 0.0 ]);
+			},
+			b"vp" => {
+				self.parameter_vertices += 1;
+				visitor.parameter_vertex([ 0.0, 0.0, 1.0 ]);
+			},
+			_ => (),
+		}
+	}
+
+	/// Handle an error in a statement. Strict mode stops; lenient mode turns it into a warning and
+	/// carries on without the statement.
+	fn skip(&self, error: WavefrontError) -> Result<Warning, WavefrontError> {
+		match self.options.mode {
+			ParseMode::Strict => Err(error),
+			ParseMode::Lenient => error.into_warning(),
+		}
+	}
+
+	/// Check nothing is left open at the end of the file, returning the material libraries named
+	fn finish<V: Visitor>(self, visitor: &mut V) -> Result<Vec<(String, Location)>, WavefrontError> {
+		if let Some(ref open) = self.body {
+			visitor.warning(self.skip(WavefrontError::InvalidElement {
+				message: format!("{} has no matching end statement", open.element.statement()),
+				location: open.location.clone(),
+			})?);
+		}
+
+		Ok(self.mtl_libs)
+	}
+}
+
+/// The object new elements are added to. Many exporters never write `o`, so geometry before the
+/// first one goes into an implicit object named according to the parse options.
+fn current_object<'a>(objects: &'a mut Vec<WavefrontObject>, options: &ParseOptions, groups: &[String]) -> &'a mut WavefrontObject {
+	if objects.is_empty() {
+		let name = match options.default_object_name {
+			DefaultObjectName::Fixed(ref name) => name.clone(),
+			DefaultObjectName::Group => groups[0].clone(),
+			DefaultObjectName::FileStem => String::from("default"),
+		};
+
+		objects.push(WavefrontObject::new(name));
+	}
+
+	objects.last_mut().unwrap()
+}
+
+/// Resolve the index of the element vertex at `position`, pointing errors at its column
+fn resolve_at(index: i64, count: usize, kind: &str, line_number: usize, line: &[u8], position: usize) -> Result<u32, WavefrontError> {
+	resolve_index(index, count, kind).map_err(|message| WavefrontError::BadIndex {
+		location: Location::new(line_number, token_column(line, position + 1), line),
+		message,
+	})
+}
+
+fn outside_body(line_number: usize, line: &[u8]) -> WavefrontError {
+	WavefrontError::UnexpectedStatement {
+		location: Location::new(line_number, token_column(line, 0), line),
+		message: format!("`{}` is only allowed between a curv, curv2 or surf statement and its end", keyword(line)),
+	}
+}
+
+fn invalid_element(line_number: usize, line: &[u8], message: String) -> WavefrontError {
+	invalid_element_at(line_number, line, 0, message)
+}
+
+/// An invalid element error pointing at the token at `position` in the statement
+fn invalid_element_at(line_number: usize, line: &[u8], position: usize, message: String) -> WavefrontError {
+	WavefrontError::InvalidElement {
+		location: Location::new(line_number, token_column(line, position), line),
+		message,
+	}
+}
+
+/// Load an OBJ file and the material libraries it references. Stops at the first malformed or
+/// unknown statement or missing material library, see `load_with_options` to skip them instead.
+pub fn load(pathname: &str) -> Result<WavefrontModel, WavefrontError> {
+	load_with_options(pathname, &ParseOptions::default())
+}
+
+pub fn load_with_options(pathname: &str, options: &ParseOptions) -> Result<WavefrontModel, WavefrontError> {
+	let path = Path::new(pathname);
+	let options = file_options(path, options);
+
+	let file = File::open(path).map_err(|error| WavefrontError::Io { path: path.to_path_buf(), error })?;
+
+	let mut builder = ModelBuilder::new(&options);
+
+	let mtl_libs = parse_stream(BufReader::new(file), &mut builder, &options).map_err(|err| err.with_path(path))?;
+
+	load_materials(path, &options, builder.model, mtl_libs)
+}
+
+/// Like `load_with_options`, but parsing on several threads with `parse_parallel`. The whole file
+/// is read into memory first.
+pub fn load_parallel(pathname: &str, options: &ParseOptions, threads: usize) -> Result<WavefrontModel, WavefrontError> {
+	let path = Path::new(pathname);
+	let options = file_options(path, options);
+
+	let mut contents = Vec::new();
+
+	File::open(path)
+		.and_then(|mut file| file.read_to_end(&mut contents))
+		.map_err(|error| WavefrontError::Io { path: path.to_path_buf(), error })?;
+
+	let (model, mtl_libs) = parse_parallel_located(&contents, &options, threads).map_err(|err| err.with_path(path))?;
+
+	load_materials(path, &options, model, mtl_libs)
+}
+
+/// Options for loading a file, with a `FileStem` default object name replaced by its stem
+fn file_options(path: &Path, options: &ParseOptions) -> ParseOptions {
+	let mut options = options.clone();
+
+	if let (&DefaultObjectName::FileStem, Some(stem)) = (&options.default_object_name, path.file_stem()) {
+		options.default_object_name = DefaultObjectName::Fixed(stem.to_string_lossy().into_owned());
+	}
+
+	options
+}
+
+/// Load the material libraries of a model parsed from `path` and finish it off. Materials from
+/// all the libraries are merged, with a later library replacing a material of the same name from
+/// an earlier one, as a later `newmtl` does within a library.
+fn load_materials(path: &Path, options: &ParseOptions, model: WavefrontModelData, mtl_libs: Vec<(String, Location)>) -> Result<WavefrontModel, WavefrontError> {
+	let mut warnings: Vec<Warning> = model.warnings.into_iter().map(|warning| warning.with_path(path)).collect();
+	let mut materials: Option<mtl::WavefrontMaterials> = None;
+
+	for (mtl_filename, location) in mtl_libs {
+		let mtl_path = path.with_file_name(mtl_filename);
+
+		let mut mtl_contents = Vec::new();
+
+		match File::open(&mtl_path).and_then(|mut file| file.read_to_end(&mut mtl_contents)) {
+			Ok(_) => {
+				let mtl_options = MtlOptions {
+					mode: options.mode,
+					illuminant: options.illuminant.clone(),
+					directory: mtl_path.parent().map(Path::to_path_buf),
+				};
+
+				let (library, mtl_warnings) = mtl::parse_with_options(&mtl_contents, &mtl_options)
+					.map_err(|err| err.with_path(&mtl_path))?;
+
+				warnings.extend(mtl_warnings.into_iter().map(|warning| warning.with_path(&mtl_path)));
+
+				materials.get_or_insert_with(mtl::WavefrontMaterials::new).extend(library);
+			},
+			Err(error) => {
+				let error = WavefrontError::MissingMaterialLibrary {
+					path: mtl_path,
+					location,
+					error,
+				}.with_path(path);
+
+				match options.mode {
+					ParseMode::Strict => return Err(error),
+					ParseMode::Lenient => warnings.push(error.into_warning()?),
+				}
+			},
+		}
+	}
+
+	let model = WavefrontModel {
+		materials,
+		vertices: model.vertices,
+		weights: model.weights,
+		normals: model.normals,
+		texcoords: model.texcoords,
+		parameter_vertices: model.parameter_vertices,
+		curves_2d: model.curves_2d,
+		connections: model.connections,
+		objects: model.objects,
+		warnings,
+		seams: OnceLock::new(),
+	};
+
+	Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::testing::assert_close;
+	use std::fs;
+	use std::io;
+
+	/// Statements setting every kind of state that carries from one statement to the next, with
+	/// continued lines and a surface body long enough that most chunk boundaries fall inside it
+	const MIXED: &str = "\
+mtllib first.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0 0.5
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1
+g left right
+s 1
+usemtl red
+f -4/-3/-1 -3/-2/-1 \\
+  -2/-1/-1
+l 1 2 3
+p 1 2
+o second
+usemtl blue
+f 1 2 4 3
+s off
+usemtl red
+f 1 3 4
+cstype bezier
+deg 1
+vp 0.1 0.1
+vp 0.9 0.1
+vp 0.9 0.9
+vp 0.1 0.9
+curv2 -4 -3 -2 -1 -4
+parm u 0 1 2 3 4
+end
+vp 0.3 0.3
+vp 0.7 0.3
+vp 0.7 0.7
+vp 0.3 0.7
+curv2 5 6 7 8 5
+parm u 0 1 \\
+  2 3 4
+end
+deg 1 1
+surf 0 1 0 1 1 2 3 4
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+hole 0 4 2
+trim 0 4 1
+hole 0 4 2
+trim 0 4 1
+hole 0 4 2
+trim 0 4 1
+hole 0 4 2
+trim 0 4 1
+hole 0 4 2
+trim 0 4 1
+hole 0 4 2
+end
+surf 0 1 0 1 1 2 3 4
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+con 1 0 4 1 2 0 4 1
+mtllib second.mtl
+g
+o third
+f 1 2 3
+";
+
+	/// Statements that fail in several ways, each skipped with a warning in lenient mode
+	const BROKEN: &str = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 4
+bevel on
+frobnicate 1 2
+v 1 x 0
+cstype bezier
+deg 1
+vp 0 0
+vp 1 0
+curv2 1 2
+v 2 2 2
+parm u 0 \\
+  1
+end
+f 1 2 -1
+curv2 1 2
+parm u 0 1
+";
+
+	fn parse_lenient(input: &str) -> WavefrontModelData {
+		let options = ParseOptions { mode: ParseMode::Lenient, ..ParseOptions::default() };
+
+		parse_with_options(input.as_bytes(), &options).unwrap().0
+	}
+
+	fn assert_parallel_matches(input: &str) {
+		for &mode in &[ ParseMode::Strict, ParseMode::Lenient ] {
+			let options = ParseOptions { mode, ..ParseOptions::default() };
+			let sequential = parse_with_options(input.as_bytes(), &options);
+
+			for threads in 0..=8 {
+				match (&sequential, parse_parallel(input.as_bytes(), &options, threads)) {
+					(Ok(sequential), Ok(parallel)) => assert_eq!(*sequential, parallel, "{:?} mode, {} threads", mode, threads),
+					(Err(sequential), Err(parallel)) => assert_eq!(sequential.to_string(), parallel.to_string(), "{:?} mode, {} threads", mode, threads),
+					(sequential, parallel) => panic!("{:?} mode, {} threads: {:?} but {:?}", mode, threads, sequential, parallel),
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn parallel_matches_sequential() {
+		let strict = ParseOptions { mode: ParseMode::Strict, ..ParseOptions::default() };
+
+		assert_eq!(parse_with_options(MIXED.as_bytes(), &strict).unwrap().0.objects().len(), 3);
+		assert_eq!(parse_lenient(BROKEN).warnings().len(), 6);
+		assert!(parse(BROKEN.as_bytes()).is_err());
+
+		assert_parallel_matches(MIXED);
+		assert_parallel_matches(BROKEN);
+	}
+
+	#[test]
+	fn parallel_chunks_start_inside_bodies() {
+		// Make sure the test above splits inside a free-form body at least once
+		let start = MIXED.find("surf").unwrap();
+		let end = start + MIXED[start..].find("end").unwrap();
+
+		let inside = (2..=8)
+			.flat_map(|threads| lines::split(MIXED.as_bytes(), threads))
+			.any(|(_, chunk)| {
+				let offset = chunk.as_ptr() as usize - MIXED.as_ptr() as usize;
+
+				offset > start && offset < end
+			});
+
+		assert!(inside);
+	}
+
+	#[test]
+	fn read_two_cubes() {
+		let model = load("assets/twocubes.obj").unwrap();
+		let names: Vec<&str> = model.objects().iter().map(|object| object.name()).collect();
+
+		assert_eq!(names, vec![ "Cube.001", "Cube" ]);
+		assert_eq!(model.vertices().len(), 16);
+		assert_eq!(model.normals().len(), 12);
+		assert!(model.texcoords().is_empty());
+		assert!(model.weights().iter().all(|&weight| weight == 1.0));
+		assert!(model.warnings().is_empty());
+		assert_eq!(model.materials().unwrap().len(), 2);
+
+		for (object, &(material, diffuse)) in model.objects().iter().zip(&[ ("Material", [ 0.64, 0.64, 0.0 ]), ("Front_Cube", [ 0.0, 0.009077, 0.64 ]) ]) {
+			assert_eq!(object.meshes().len(), 1);
+
+			let mesh = &object.meshes()[0];
+
+			assert_eq!(mesh.material_name(), Some(material));
+			assert_eq!(model.mesh_material(mesh).unwrap().diffuse, diffuse);
+			assert_eq!(object.mesh_faces(mesh).len(), object.faces().len());
+			assert!(object.lines().is_empty() && object.points().is_empty());
+
+			for face in object.faces() {
+				assert_eq!(face.smoothing_group(), 0);
+				assert_eq!(face.vertices().len(), 3);
+				assert!(face.has_normals() && !face.has_texcoords());
+			}
+		}
+
+		assert_eq!(model.objects()[0].faces().len(), 12);
+		assert_eq!(model.object("Cube").unwrap().faces().len(), 12);
+		assert!(model.object("Cone").is_none());
+
+		// Indices are zero based and shared across objects
+		let face = &model.objects()[1].faces()[0];
+		let corners: Vec<(u32, Option<u32>)> = face.vertices().iter().map(|v| (v.vertex, v.normal)).collect();
+
+		assert_eq!(corners, vec![ (9, Some(6)), (11, Some(6)), (8, Some(6)) ]);
+		assert_eq!(model.vertices()[9], [ 2.700047, -1.0, -1.0 ]);
+		assert_eq!(model.normals()[6], [ 0.0, 0.0, -1.0 ]);
+	}
+
+	/// Faces in overlapping groups, with group `b` carrying on into a second object
+	const GROUPS: &str = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+o first
+g a b
+f 1 2 3
+g a
+f 2 4 3
+o second
+g b
+f 1 2 4
+";
+
+	/// A model without materials parsed from `input`
+	fn model(input: &str) -> WavefrontModel {
+		let (data, _) = parse(input.as_bytes()).unwrap();
+
+		load_materials(Path::new("model.obj"), &ParseOptions::default(), data, Vec::new()).unwrap()
+	}
+
+	#[test]
+	fn faces_in_several_groups() {
+		let (model, _) = parse(GROUPS.as_bytes()).unwrap();
+		let faces = model.objects()[0].faces();
+
+		assert_eq!(faces[0].groups(), [ "a", "b" ]);
+		assert!(faces[0].in_group("a") && faces[0].in_group("b"));
+		assert_eq!(faces[1].groups(), [ "a" ]);
+		assert!(!faces[1].in_group("b"));
+	}
+
+	#[test]
+	fn groups_span_objects() {
+		let model = model(GROUPS);
+
+		assert_eq!(model.group_names(), vec![ "a", "b" ]);
+
+		let faces: Vec<(&str, u32)> = model.group_faces("b").iter()
+			.map(|&(object, face)| (object.name(), face.vertices()[2].vertex))
+			.collect();
+
+		assert_eq!(faces, vec![ ("first", 2), ("second", 3) ]);
+		assert_eq!(model.group_faces("a").len(), 2);
+		assert!(model.group_faces("c").is_empty());
+	}
+
+	#[test]
+	fn extract_group_keeps_indices() {
+		let model = model(&format!("{}\
+vp 0 0
+vp 1 0
+vp 1 1
+vp 0 1
+cstype bezier
+deg 1
+curv2 1 2 3 4 1
+parm u 0 1 2 3 4
+end
+deg 1 1
+g a
+surf 0 1 0 1 1 2 3 4
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+g b
+surf 0 1 0 1 1 2 3 4
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+g a
+surf 0 1 0 1 1 2 3 4
+parm u 0 1
+parm v 0 1
+trim 0 4 1
+end
+con 1 0 4 1 2 0 4 1
+con 1 0 4 1 3 0 4 1
+", GROUPS));
+
+		assert_eq!(model.connections().len(), 2);
+
+		let extracted = model.extract_group("a");
+		let names: Vec<&str> = extracted.objects().iter().map(|object| object.name()).collect();
+
+		// The surfaces follow the face of the second object, which is only in group `b`
+		assert_eq!(names, vec![ "first", "second" ]);
+		assert_eq!(extracted.objects()[0].faces(), model.objects()[0].faces());
+		assert!(extracted.objects()[1].faces().is_empty());
+		assert_eq!(extracted.objects()[1].surfaces().len(), 2);
+
+		// Pools are kept whole so face and control point indices still refer to the same vertices
+		assert_eq!(extracted.vertices(), model.vertices());
+		assert_eq!(extracted.parameter_vertices(), model.parameter_vertices());
+		assert_eq!(extracted.curves_2d(), model.curves_2d());
+
+		// Only the connection between the first and third surface is kept, which are now the first
+		// and second
+		let connection = &extracted.connections()[0];
+
+		assert_eq!(extracted.connections().len(), 1);
+		assert_eq!((connection.first.surface, connection.second.surface), (0, 1));
+		assert_eq!(extracted.surface(1), model.surface(2));
+
+		assert!(model.extract_group("c").objects().is_empty());
+		assert_eq!(model.extract_group("b").objects()[0].faces(), &model.objects()[0].faces()[0..1]);
+	}
+
+	#[test]
+	fn materials_switching_back() {
+		let model = model("\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+o first
+usemtl A
+f 1 2 3
+f 1 2 3
+usemtl B
+f 1 2 3
+usemtl A
+f 1 2 3
+o second
+f 1 2 3
+usemtl B
+f 1 2 3
+");
+		let first = &model.objects()[0];
+		let meshes: Vec<(Option<&str>, Range<usize>)> = first.meshes().iter()
+			.map(|mesh| (mesh.material_name(), mesh.face_range()))
+			.collect();
+
+		// Switching back to a material starts a new mesh, and meshes are never empty
+		assert_eq!(meshes, vec![ (Some("A"), 0..2), (Some("B"), 2..3), (Some("A"), 3..4) ]);
+
+		// The material carries on into the next object
+		let second: Vec<Option<&str>> = model.objects()[1].meshes().iter().map(|mesh| mesh.material_name()).collect();
+
+		assert_eq!(second, vec![ Some("A"), Some("B") ]);
+
+		// One batch per object and material, with meshes using the same material merged
+		let batches: Vec<usize> = model.to_vertices().iter().map(|(vertices, _)| vertices.len()).collect();
+
+		assert_eq!(batches, vec![ 9, 3, 3, 3 ]);
+	}
+
+	/// Geometry before the first `o` statement, in a group
+	const IMPLICIT: &str = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+g front back
+f 1 2 3
+o named
+f 1 2 3
+";
+
+	fn object_names(model: &WavefrontModelData) -> Vec<&str> {
+		model.objects().iter().map(|object| object.name()).collect()
+	}
+
+	#[test]
+	fn default_object_names() {
+		let named = |default_object_name| {
+			let options = ParseOptions { default_object_name, ..ParseOptions::default() };
+			let (model, _) = parse_with_options(IMPLICIT.as_bytes(), &options).unwrap();
+
+			assert_eq!(model.objects()[0].faces().len(), 1);
+			assert_eq!(model.objects()[1].faces().len(), 1);
+
+			object_names(&model).join(" ")
+		};
+
+		assert_eq!(named(DefaultObjectName::Fixed(String::from("unnamed"))), "unnamed named");
+		assert_eq!(named(DefaultObjectName::Group), "front named");
+		assert_eq!(named(DefaultObjectName::FileStem), "default named");
+		assert_eq!(object_names(&parse(IMPLICIT.as_bytes()).unwrap().0), vec![ "default", "named" ]);
+
+		// Without geometry before it, `o` doesn't leave an empty default object behind
+		assert_eq!(object_names(&parse(b"v 0 0 0\no named\np 1\n").unwrap().0), vec![ "named" ]);
+	}
+
+	#[test]
+	fn file_stem_object_name() {
+		let path = std::env::temp_dir().join(format!("obj-rs-stem-{}.obj", std::process::id()));
+
+		fs::write(&path, IMPLICIT).unwrap();
+
+		let options = ParseOptions { default_object_name: DefaultObjectName::FileStem, ..ParseOptions::default() };
+		let model = load_with_options(path.to_str().unwrap(), &options);
+
+		fs::remove_file(&path).unwrap();
+
+		let stem = path.file_stem().unwrap().to_str().unwrap();
+		let names: Vec<&str> = model.as_ref().unwrap().objects().iter().map(|object| object.name()).collect();
+
+		assert_eq!(names, vec![ stem, "named" ]);
+	}
+
+	#[test]
+	fn parallel_chunks_continue_objects() {
+		// Chunks after the first start without an `o` statement, so their geometry must be
+		// appended to the object the previous chunk ended in rather than a default object
+		let faces = "f 1 2 3\n".repeat(40);
+		let input = format!("{}{}o second\n{}", IMPLICIT, faces, faces);
+		let options = ParseOptions::default();
+		let (sequential, _) = parse(input.as_bytes()).unwrap();
+
+		assert_eq!(object_names(&sequential), vec![ "default", "named", "second" ]);
+
+		for threads in 2..=8 {
+			assert!(lines::split(input.as_bytes(), threads).len() > 1);
+
+			let (parallel, _) = parse_parallel(input.as_bytes(), &options, threads).unwrap();
+
+			assert_eq!(object_names(&parallel), object_names(&sequential));
+			assert_eq!(parallel.objects()[1].faces().len(), 41);
+			assert_eq!(parallel.objects()[1].meshes().len(), 1);
+			assert_eq!(parallel, sequential);
+		}
+	}
+
+	#[test]
+	fn line_and_point_vertices() {
+		let model = model("\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+usemtl wire
+l 1/1 2/2 -1/-1
+l 3 1
+p 1 2
+usemtl dots
+p -1
+f 1 2 3
+");
+		let object = &model.objects()[0];
+		let line: Vec<(u32, Option<u32>)> = object.lines()[0].vertices().iter().map(|v| (v.vertex, v.texcoord)).collect();
+
+		assert_eq!(line, vec![ (0, Some(0)), (1, Some(1)), (2, Some(2)) ]);
+		assert_eq!(object.lines()[1].vertices()[0], LineVertex { vertex: 2, texcoord: None });
+		assert_eq!(object.points()[0].vertices(), [ 0, 1 ]);
+		assert_eq!(object.points()[1].vertices(), [ 2 ]);
+
+		let positions = |batches: Vec<(Vec<BufferVertex>, mtl::WavefrontMaterial)>| -> Vec<Vec<[f32; 3]>> {
+			batches.into_iter()
+				.map(|(vertices, _)| {
+					assert!(vertices.iter().all(|vertex| vertex.normal == [ 0.0, 0.0, 0.0 ]));
+
+					vertices.iter().map(|vertex| vertex.position).collect()
+				})
+				.collect()
+		};
+
+		// Each line is split into segments, and the batch for the second material has no lines
+		assert_eq!(positions(model.to_line_vertices()), vec![ vec![
+			[ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ],
+			[ 1.0, 0.0, 0.0 ], [ 1.0, 1.0, 0.0 ],
+			[ 1.0, 1.0, 0.0 ], [ 0.0, 0.0, 0.0 ],
+		] ]);
+
+		assert_eq!(positions(model.to_point_vertices()), vec![
+			vec![ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ] ],
+			vec![ [ 1.0, 1.0, 0.0 ] ],
+		]);
+
+		assert_eq!(model.to_vertices().len(), 1);
+	}
+
+	/// Parse in strict mode after declaring eight vertices and two parameter vertices
+	fn parse_strict(statements: &str) -> Result<WavefrontModelData, WavefrontError> {
+		let input = format!("{}vp 0 0\nvp 1 1\n{}", "v 0 0 0\n".repeat(8), statements);
+		let options = ParseOptions { mode: ParseMode::Strict, ..ParseOptions::default() };
+
+		parse_with_options(input.as_bytes(), &options).map(|(model, _)| model)
+	}
+
+	/// The error from `statements`, with its line number counting from the first statement
+	fn body_error(statements: &str) -> (usize, String) {
+		let error = parse_strict(statements).expect_err(statements);
+
+		(error.location().unwrap().line - 10, error.to_string())
+	}
+
+	#[test]
+	fn free_form_statement_order() {
+		let (line, message) = body_error("cstype bezier\ndeg 1\ncurv 0 1 1 2\nv 0 0 0\nparm u 0 1\nend\n");
+
+		assert_eq!(line, 4);
+		assert!(message.contains("`v`"), "{}", message);
+
+		let (line, message) = body_error("cstype bezier\ndeg 1\ncurv 0 1 1 2\nparm u 0 1\n");
+
+		assert_eq!(line, 3);
+		assert!(message.contains("curv has no matching end statement"), "{}", message);
+
+		// The body of an element needs the degree when it starts, so parm can't come first
+		let (line, message) = body_error("cstype bezier\ncurv 0 1 1 2\nparm u 0 1\nend\ndeg 1\n");
+
+		assert_eq!(line, 2);
+		assert!(message.contains("needs a deg statement"), "{}", message);
+
+		let (line, message) = body_error("cstype bezier\nparm u 0 1\ndeg 1\n");
+
+		assert_eq!(line, 2);
+		assert!(message.contains("only allowed between"), "{}", message);
+
+		let (line, message) = body_error("deg 1\ncurv 0 1 1 2\nparm u 0 1\nend\n");
+
+		assert_eq!(line, 2);
+		assert!(message.contains("needs a cstype statement"), "{}", message);
+
+		// In lenient mode only the misplaced statement is skipped, leaving a placeholder vertex
+		let model = parse_lenient("v 0 0 0\nv 1 0 0\ncstype bezier\ndeg 1\ncurv 0 1 1 2\nv 2 0 0\nparm u 0 1\nend\n");
+
+		assert_eq!(model.warnings().len(), 1);
+		assert_eq!(model.warnings()[0].location.line, 6);
+		assert_eq!(model.vertices(), [ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ], [ 0.0, 0.0, 0.0 ] ]);
+		assert_eq!(model.objects()[0].curves().len(), 1);
+	}
+
+	#[test]
+	fn control_point_counts() {
+		// Attributes for each basis and the number of control points it needs with the parameter
+		// values `0 1`, or the knot vector `0 0 0 1 1 1` for the b-spline
+		let bases = [
+			("cstype bezier\ndeg 3\n", "0 1", 4),
+			("cstype bspline\ndeg 2\n", "0 0 0 1 1 1", 3),
+			("cstype cardinal\ndeg 3\n", "0 1", 4),
+			("cstype taylor\ndeg 2\n", "0 1", 3),
+			("cstype bmatrix\ndeg 1\nstep 1\nbmat u 1 0 0 1\n", "0 1", 2),
+			("cstype rat bezier\ndeg 2\n", "0 1 2", 5),
+		];
+
+		for &(attributes, parameters, count) in bases.iter() {
+			for &points in &[ count - 1, count, count + 1 ] {
+				let indices: Vec<String> = (1..=points).map(|i| i.to_string()).collect();
+				let statements = format!("{}curv 0 1 {}\nparm u {}\nend\n", attributes, indices.join(" "), parameters);
+
+				match parse_strict(&statements) {
+					Ok(model) => {
+						assert_eq!(points, count, "{}", statements);
+						assert_eq!(model.objects()[0].curves()[0].control_points().len(), count);
+					},
+					Err(error) => {
+						assert_ne!(points, count, "{}: {}", statements, error);
+						assert!(error.to_string().contains(&format!("needs {} control points but has {}", count, points)), "{}", error);
+					},
+				}
+			}
+		}
+
+		// Surfaces need the product of the counts of each direction
+		let surface = |points: usize| {
+			let indices: Vec<String> = (1..=points).map(|i| i.to_string()).collect();
+
+			parse_strict(&format!("cstype bezier\ndeg 1 2\nsurf 0 1 0 1 {}\nparm u 0 1\nparm v 0 1\nend\n", indices.join(" ")))
+		};
+
+		assert_eq!(surface(6).unwrap().objects()[0].surfaces()[0].size(), (2, 3));
+		assert!(surface(5).unwrap_err().to_string().contains("needs 2 x 3 control points but has 5"));
+	}
+
+	#[test]
+	fn skipped_vertices_keep_their_place() {
+		let input = "\
+v 0 0 0 x
+v 1 0 0
+v 0 1 0
+v 5 5 5
+vt 0 0
+vt 1 y
+vt 1 1
+vn 0 0 1 z
+vn 1 0 0
+vp 0 0 0 0
+vp 1 0
+f 2/3/2 3/3/2 4/3/2
+f -3/-1/-1 -2/-1/-1 -1/-1/-1
+";
+		let model = parse_lenient(input);
+		let faces = model.objects()[0].faces();
+
+		assert_eq!(model.warnings().len(), 4);
+		assert_eq!(model.vertices(), [ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ], [ 0.0, 1.0, 0.0 ], [ 5.0, 5.0, 5.0 ] ]);
+		assert_eq!(model.texcoords()[1], [ 0.0, 0.0, 0.0 ]);
+		assert_eq!(model.normals(), [ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ] ]);
+		assert_eq!(model.parameter_vertices(), [ [ 0.0, 0.0, 1.0 ], [ 1.0, 0.0, 1.0 ] ]);
+
+		// Both absolute and relative indices refer to the vertices as numbered in the file
+		for face in faces {
+			let corners: Vec<(u32, Option<u32>, Option<u32>)> = face.vertices().iter().map(|v| (v.vertex, v.texcoord, v.normal)).collect();
+
+			assert_eq!(corners, vec![ (1, Some(2), Some(1)), (2, Some(2), Some(1)), (3, Some(2), Some(1)) ]);
+		}
+
+		assert_parallel_matches(input);
+	}
+
+	/// Visitor keeping the vertices, faces and warnings it is given
+	#[derive(Default)]
+	struct Recorder {
+		vertices: Vec<[f32; 3]>,
+		faces: Vec<Face>,
+		warnings: Vec<Warning>,
+	}
+
+	impl Visitor for Recorder {
+		fn vertex(&mut self, position: [f32; 3], _weight: f32) {
+			self.vertices.push(position);
+		}
+
+		fn face(&mut self, face: Face) {
+			self.faces.push(face);
+		}
+
+		fn warning(&mut self, warning: Warning) {
+			self.warnings.push(warning);
+		}
+	}
+
+	#[test]
+	fn reader_matches_parse() {
+		let mut input = String::new();
+		let mut line_number = 1;
+
+		// A face continued over the end of the first block, and a bad face after it
+		while input.len() < BLOCK_SIZE - 20 {
+			input.push_str(&format!("v {} 0 0\n", line_number % 10));
+			line_number += 1;
+		}
+
+		let continued = input.len();
+
+		input.push_str("f 1 \\\n2 \\\n3 \\\n4 \\\n5\n");
+		assert!(continued < BLOCK_SIZE && input.len() > BLOCK_SIZE);
+		input.push_str("f 1 2 \\\n  99999\n");
+
+		// A second bad face well into the second block
+		input.push_str(&"v 1 1 1\n".repeat(1000));
+		input.push_str("f 1 2 -99999\nf -1 -2 -3\n");
+
+		let options = ParseOptions { mode: ParseMode::Lenient, ..ParseOptions::default() };
+		let mut recorder = Recorder::default();
+
+		parse_reader(io::BufReader::with_capacity(100, input.as_bytes()), &mut recorder, &options).unwrap();
+
+		let (model, _) = parse_with_options(input.as_bytes(), &options).unwrap();
+
+		assert_eq!(recorder.vertices, model.vertices());
+		assert_eq!(recorder.faces, model.objects()[0].faces());
+		assert_eq!(recorder.warnings, model.warnings());
+
+		// The continued face is read once, and the errors are on the lines they were made
+		assert_eq!(recorder.faces.len(), 2);
+		assert_eq!(recorder.faces[0].vertices().len(), 5);
+
+		let lines: Vec<usize> = recorder.warnings.iter().map(|warning| warning.location.line).collect();
+
+		assert_eq!(lines, vec![ line_number + 6, line_number + 1007 ]);
+	}
+
+	/// Two faces meeting at a ridge along vertices 3 and 4, both sloping down away from it
+	const ROOF: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 1
+v 0 1 1
+v 0 2 0
+v 1 2 0
+";
+
+	/// The generated normals of the corners of each face of `ROOF` followed by `faces`
+	fn roof_normals(faces: &str) -> Vec<Vec<[f32; 3]>> {
+		let input = format!("{}{}", ROOF, faces);
+		let (mut model, _) = parse(input.as_bytes()).unwrap();
+
+		model.generate_normals();
+
+		model.objects()[0].faces().iter()
+			.map(|face| face.vertices().iter().map(|v| model.normals()[v.normal.unwrap() as usize]).collect())
+			.collect()
+	}
+
+	#[test]
+	fn smooth_normals_are_shared() {
+		let normals = roof_normals("s 1\nf 1 2 3 4\nf 4 3 6 5\n");
+		let slope = 0.5f32.sqrt();
+
+		// The ridge is shared, so its vertices average the two slopes
+		assert_close(normals[0][2], [ 0.0, 0.0, 1.0 ], 1e-5);
+		assert_close(normals[0][3], [ 0.0, 0.0, 1.0 ], 1e-5);
+		assert_eq!(normals[0][2], normals[1][1]);
+		assert_eq!(normals[0][3], normals[1][0]);
+
+		// The eaves only touch one face each
+		assert_close(normals[0][0], [ 0.0, -slope, slope ], 1e-5);
+		assert_close(normals[1][3], [ 0.0, slope, slope ], 1e-5);
+	}
+
+	#[test]
+	fn flat_normals_without_smoothing() {
+		let slope = 0.5f32.sqrt();
+
+		for smoothing in &[ "", "s 0\n", "s off\n" ] {
+			let normals = roof_normals(&format!("{}f 1 2 3 4\nf 4 3 6 5\n", smoothing));
+
+			for normal in normals[0].iter() {
+				assert_close(*normal, [ 0.0, -slope, slope ], 1e-5);
+			}
+
+			for normal in normals[1].iter() {
+				assert_close(*normal, [ 0.0, slope, slope ], 1e-5);
+			}
+		}
+	}
+
+	#[test]
+	fn smoothing_groups_keep_hard_edges() {
+		let normals = roof_normals("s 1\nf 1 2 3 4\ns 2\nf 4 3 6 5\n");
+
+		assert_ne!(normals[0][2], normals[1][1]);
+		assert_close(normals[0][2], normals[0][0], 1e-5);
+	}
+
+	#[test]
+	fn given_normals_are_kept() {
+		let input = format!("{}vn 1 0 0\ns 1\nf 1//1 2//1 3//1\nf 4 3 6 5\n", ROOF);
+		let (mut model, _) = parse(input.as_bytes()).unwrap();
+
+		model.generate_normals();
+
+		let faces = model.objects()[0].faces();
+
+		assert!(faces[0].vertices().iter().all(|v| v.normal == Some(0)));
+		assert!(faces[1].vertices().iter().all(|v| v.normal.is_some_and(|normal| normal > 0)));
+		assert_eq!(model.normals().len(), 5);
+	}
+
+	#[test]
+	fn parsing_keeps_pools_raw() {
+		for faces in &[ "s 1\nf 1 2 3 4\nf 4 3 6 5\n", "s 1\nf 1 2 3 4\ns off\nf 4 3 6 5\n" ] {
+			let input = format!("{}vn 1 0 0\n{}f 1//1 2//1 5//1\n", ROOF, faces);
+			let (data, _) = parse(input.as_bytes()).unwrap();
+			let faces = data.objects()[0].faces();
+
+			assert_eq!(data.normals(), [ [ 1.0, 0.0, 0.0 ] ]);
+			assert!(!faces[0].has_normals() && !faces[1].has_normals());
+			assert!(faces[0].vertices().iter().all(|v| v.normal.is_none()));
+
+			// Shading without normals doesn't touch the model and matches generating them first
+			let mut generated = model(&input);
+			let model = model(&input);
+			let shaded = |model: &WavefrontModel| -> Vec<Vec<BufferVertex>> {
+				model.to_vertices().into_iter().map(|(vertices, _)| vertices).collect()
+			};
+
+			generated.generate_normals();
+
+			assert_eq!(shaded(&model), shaded(&generated));
+			assert_eq!(model.normals().len(), 1);
+			assert!(generated.normals().len() > 1);
+		}
+	}
+
+	#[test]
+	fn several_material_libraries() {
+		let input = "mtllib a.mtl b.mtl\nmtllib c.mtl\tsub/d.mtl a.mtl\n";
+		let (_, names) = parse(input.as_bytes()).unwrap();
+
+		assert_eq!(names, vec![ "a.mtl", "b.mtl", "c.mtl", "sub/d.mtl" ]);
+		assert_eq!(parse_parallel(input.as_bytes(), &ParseOptions::default(), 2).unwrap().1, names);
+	}
+
+	#[test]
+	fn material_libraries_are_merged() {
+		let directory = std::env::temp_dir().join(format!("obj-rs-mtllib-{}", std::process::id()));
+		let path = directory.join("model.obj");
+
+		fs::create_dir_all(&directory).unwrap();
+		fs::write(&path, "mtllib first.mtl second.mtl\nmtllib missing.mtl\nv 0 0 0\n").unwrap();
+		fs::write(directory.join("first.mtl"), "newmtl red\nKd 1 0 0\nnewmtl shared\nKd 1 1 1\n").unwrap();
+		fs::write(directory.join("second.mtl"), "newmtl blue\nKd 0 0 1\nnewmtl shared\nKd 0 1 0\n").unwrap();
+
+		let lenient = load_with_options(path.to_str().unwrap(), &ParseOptions { mode: ParseMode::Lenient, ..ParseOptions::default() });
+		let strict = load(path.to_str().unwrap());
+
+		fs::remove_dir_all(&directory).unwrap();
+
+		let model = lenient.unwrap();
+		let materials = model.materials().unwrap();
+
+		assert_eq!(materials.len(), 3);
+		assert_eq!(materials["red"].diffuse, [ 1.0, 0.0, 0.0 ]);
+		assert_eq!(materials["blue"].diffuse, [ 0.0, 0.0, 1.0 ]);
+		assert_eq!(materials["shared"].diffuse, [ 0.0, 1.0, 0.0 ]);
+		assert_eq!(model.warnings().len(), 1);
+		assert_eq!((model.warnings()[0].location.line, model.warnings()[0].location.column), (2, 8));
+
+		assert!(matches!(strict, Err(WavefrontError::MissingMaterialLibrary { .. })));
+	}
+}
@@ -0,0 +1,327 @@
+//! Conversion of spectral and CIE XYZ colours from MTL files to RGB.
+//!
+//! The `.rfl` files named by `spectral` statements are text, with `#` starting a comment, and
+//! hold the reflectance or transmittance at wavelengths in nanometres in one of two layouts:
+//!
+//! - An `sp` statement with the first and last wavelength, followed by values evenly spaced from
+//!   the first wavelength to the last, any number to a line.
+//! - One sample to a line, a wavelength and the value at it, for unevenly spaced samples.
+//!
+//! The curve is lit by an illuminant and integrated against the CIE 1931 colour matching
+//! functions, so a perfect white reflector has a luminance of 1. The result is adapted from the
+//! white of the illuminant to D65, the white of sRGB, so a flat curve is grey under any
+//! illuminant, then converted to linear sRGB. Colours outside the sRGB gamut may have components
+//! below 0 or above 1.
+
+use std::f32;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// Light a spectral curve is viewed under when converting it to RGB
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Illuminant {
+	/// CIE standard illuminant D65, average daylight and the white point of sRGB
+	#[default]
+	D65,
+	/// CIE standard illuminant A, incandescent light from a black body at 2856 K
+	A,
+	/// Equal power at every wavelength
+	E,
+	/// A black body at a temperature in kelvin
+	BlackBody(f32),
+	/// A measured spectrum as pairs of wavelength in nanometres and relative power, in order of
+	/// increasing wavelength
+	Spectrum(Vec<(f32, f32)>),
+}
+
+/// Relative power of CIE illuminant D65 every 10 nm from 380 nm to 780 nm
+const D65: [f32; 41] = [
+	49.98, 54.65, 82.75, 91.49, 93.43, 86.68, 104.86, 117.01, 117.81, 114.86,
+	115.92, 108.81, 109.35, 107.80, 104.79, 107.69, 104.41, 104.05, 100.00, 96.33,
+	95.79, 88.69, 90.01, 89.60, 87.70, 83.29, 83.70, 80.03, 80.21, 82.28,
+	78.28, 69.72, 71.61, 74.35, 61.60, 69.89, 75.09, 63.59, 46.42, 66.81,
+	63.38,
+];
+
+/// Range of visible wavelengths integrated over, in nanometres
+const VISIBLE: (f32, f32) = (380.0, 780.0);
+
+/// Step between the wavelengths integrated over, in nanometres
+const STEP: f32 = 5.0;
+
+/// CIE XYZ of the D65 white point, the white of sRGB
+const D65_WHITE: [f32; 3] = [ 0.95047, 1.0, 1.08883 ];
+
+/// Bradford transform from CIE XYZ to cone responses
+const BRADFORD: [[f32; 3]; 3] = [
+	[ 0.8951, 0.2664, -0.1614 ],
+	[ -0.7502, 1.7135, 0.0367 ],
+	[ 0.0389, -0.0685, 1.0296 ],
+];
+
+/// Inverse of `BRADFORD`
+const BRADFORD_INVERSE: [[f32; 3]; 3] = [
+	[ 0.986_993, -0.147_054, 0.159_963 ],
+	[ 0.432_305, 0.518_360, 0.049_291 ],
+	[ -0.008_529, 0.040_043, 0.968_487 ],
+];
+
+impl Illuminant {
+	/// Relative power at a wavelength in nanometres
+	fn power(&self, wavelength: f32) -> f32 {
+		match *self {
+			Illuminant::D65 => {
+				let position = ((wavelength - VISIBLE.0) / 10.0).clamp(0.0, (D65.len() - 1) as f32);
+				let index = (position as usize).min(D65.len() - 2);
+
+				D65[index] + (D65[index + 1] - D65[index]) * (position - index as f32)
+			},
+			Illuminant::A => black_body(2856.0, wavelength),
+			Illuminant::E => 1.0,
+			Illuminant::BlackBody(temperature) => black_body(temperature, wavelength),
+			Illuminant::Spectrum(ref samples) => sample(samples, wavelength),
+		}
+	}
+}
+
+/// Spectral radiance of a black body, up to a constant factor
+fn black_body(temperature: f32, wavelength: f32) -> f32 {
+	// Second radiation constant in nanometre kelvins
+	const C2: f64 = 1.4388e7;
+
+	let wavelength = f64::from(wavelength);
+
+	(1.0 / (wavelength.powi(5) * ((C2 / (wavelength * f64::from(temperature))).exp() - 1.0)) * 1e15) as f32
+}
+
+/// Value of a sampled curve at a wavelength, interpolating linearly between samples and holding
+/// the end values beyond them
+fn sample(samples: &[(f32, f32)], wavelength: f32) -> f32 {
+	match samples.iter().position(|&(sample, _)| sample >= wavelength) {
+		Some(0) => samples[0].1,
+		Some(index) => {
+			let (w0, v0) = samples[index - 1];
+			let (w1, v1) = samples[index];
+
+			v0 + (v1 - v0) * (wavelength - w0) / (w1 - w0)
+		},
+		None => samples.last().map_or(0.0, |&(_, value)| value),
+	}
+}
+
+/// Gaussian with different widths either side of its peak
+fn lobe(wavelength: f32, peak: f32, below: f32, above: f32) -> f32 {
+	let width = if wavelength < peak { below } else { above };
+	let t = (wavelength - peak) / width;
+
+	(-0.5 * t * t).exp()
+}
+
+/// The CIE 1931 2° colour matching functions at a wavelength in nanometres, using the
+/// multi-lobe fit of Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ
+/// Color Matching Functions" (2013)
+fn color_matching(wavelength: f32) -> [f32; 3] {
+	[
+		1.056 * lobe(wavelength, 599.8, 37.9, 31.0) + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+			- 0.065 * lobe(wavelength, 501.1, 20.4, 26.2),
+		0.821 * lobe(wavelength, 568.8, 46.9, 40.5) + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1),
+		1.217 * lobe(wavelength, 437.0, 11.8, 36.0) + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8),
+	]
+}
+
+/// CIE XYZ of a spectral curve lit by an illuminant, scaled so a curve of 1 everywhere has Y = 1
+pub fn spectrum_to_xyz(curve: &[(f32, f32)], illuminant: &Illuminant) -> [f32; 3] {
+	let mut xyz = [ 0.0; 3 ];
+	let mut white = 0.0;
+	let steps = ((VISIBLE.1 - VISIBLE.0) / STEP) as usize;
+
+	for step in 0..steps + 1 {
+		let wavelength = VISIBLE.0 + step as f32 * STEP;
+		let power = illuminant.power(wavelength);
+		let matching = color_matching(wavelength);
+		let value = sample(curve, wavelength);
+
+		for axis in 0..3 {
+			xyz[axis] += value * power * matching[axis];
+		}
+
+		white += power * matching[1];
+	}
+
+	if white > 0.0 {
+		[ xyz[0] / white, xyz[1] / white, xyz[2] / white ]
+	} else {
+		[ 0.0; 3 ]
+	}
+}
+
+fn transform(matrix: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+	let row = |r: [f32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+
+	[ row(matrix[0]), row(matrix[1]), row(matrix[2]) ]
+}
+
+/// Adapt CIE XYZ seen under a light with the white point `from` to how it would look under D65,
+/// using the Bradford transform
+fn adapt_to_d65(xyz: [f32; 3], from: [f32; 3]) -> [f32; 3] {
+	let source = transform(&BRADFORD, from);
+	let target = transform(&BRADFORD, D65_WHITE);
+
+	if source.iter().any(|&response| response <= 0.0) {
+		return xyz;
+	}
+
+	let cone = transform(&BRADFORD, xyz);
+	let scaled = [ cone[0] * target[0] / source[0], cone[1] * target[1] / source[1], cone[2] * target[2] / source[2] ];
+
+	transform(&BRADFORD_INVERSE, scaled)
+}
+
+/// Convert CIE XYZ to linear sRGB
+pub fn xyz_to_rgb(xyz: [f32; 3]) -> [f32; 3] {
+	let [ x, y, z ] = xyz;
+
+	[
+		3.2406 * x - 1.5372 * y - 0.4986 * z,
+		-0.9689 * x + 1.8758 * y + 0.0415 * z,
+		0.0557 * x - 0.2040 * y + 1.0570 * z,
+	]
+}
+
+/// Linear sRGB of a spectral curve lit by an illuminant, adapted to the white of sRGB
+pub fn spectrum_to_rgb(curve: &[(f32, f32)], illuminant: &Illuminant) -> [f32; 3] {
+	let white = spectrum_to_xyz(&[ (VISIBLE.0, 1.0) ], illuminant);
+
+	xyz_to_rgb(adapt_to_d65(spectrum_to_xyz(curve, illuminant), white))
+}
+
+/// Read a spectral curve from an `.rfl` file in either layout described in the module
+/// documentation, returning a message describing what went wrong if it can't be read
+pub fn load_curve(path: &Path) -> Result<Vec<(f32, f32)>, String> {
+	let mut contents = String::new();
+
+	File::open(path)
+		.and_then(|mut file| file.read_to_string(&mut contents))
+		.map_err(|error| format!("couldn't read spectral curve {}: {}", path.display(), error))?;
+
+	parse_curve(&contents).map_err(|(line, message)| format!("{}:{}: {}", path.display(), line, message))
+}
+
+/// Parse the contents of an `.rfl` file, returning the line number and message of any error
+fn parse_curve(contents: &str) -> Result<Vec<(f32, f32)>, (usize, String)> {
+	// Line numbers and the tokens before any comment, for the lines with any
+	let lines: Vec<(usize, Vec<&str>)> = contents.lines()
+		.enumerate()
+		.map(|(index, line)| (index + 1, line.split('#').next().unwrap_or("").split_whitespace().collect::<Vec<_>>()))
+		.filter(|(_, tokens)| !tokens.is_empty())
+		.collect();
+
+	match lines.first() {
+		Some((_, tokens)) if tokens[0] == "sp" => parse_sampled(&lines),
+		Some(_) => parse_pairs(&lines),
+		None => Err((1, String::from("spectral curve has no samples"))),
+	}
+}
+
+/// Parse an `sp` statement and the evenly spaced values following it
+fn parse_sampled(lines: &[(usize, Vec<&str>)]) -> Result<Vec<(f32, f32)>, (usize, String)> {
+	let (header, ref tokens) = lines[0];
+
+	let range = match tokens[..] {
+		[ _, first, last, .. ] => first.parse::<f32>().ok().zip(last.parse::<f32>().ok()),
+		_ => None,
+	};
+
+	let (first, last) = range.ok_or_else(|| (header, String::from("expected `sp` with the first and last wavelength")))?;
+
+	if last <= first {
+		return Err((header, String::from("the last wavelength must be greater than the first")));
+	}
+
+	// Values may follow the wavelengths on the `sp` line itself
+	let mut values = Vec::new();
+	let rest = lines.iter().map(|(line, tokens)| (*line, &tokens[..])).skip(1);
+
+	for (line, tokens) in Some((header, &tokens[3..])).into_iter().chain(rest) {
+		for token in tokens {
+			values.push(token.parse::<f32>().map_err(|_| (line, format!("expected a value, found `{}`", token)))?);
+		}
+	}
+
+	if values.len() < 2 {
+		return Err((header, String::from("evenly spaced samples need at least two values")));
+	}
+
+	let step = (last - first) / (values.len() - 1) as f32;
+
+	Ok(values.into_iter().enumerate().map(|(index, value)| (first + index as f32 * step, value)).collect())
+}
+
+/// Parse one wavelength and value pair per line
+fn parse_pairs(lines: &[(usize, Vec<&str>)]) -> Result<Vec<(f32, f32)>, (usize, String)> {
+	let mut curve: Vec<(f32, f32)> = Vec::new();
+
+	for &(line, ref tokens) in lines {
+		let sample = match tokens[..] {
+			[ wavelength, value ] => wavelength.parse::<f32>().ok().zip(value.parse::<f32>().ok()),
+			_ => None,
+		};
+
+		let (wavelength, value) = sample.ok_or_else(|| (line, String::from("expected a wavelength and a value")))?;
+
+		if curve.last().is_some_and(|&(last, _)| wavelength <= last) {
+			return Err((line, String::from("wavelengths must increase from one sample to the next")));
+		}
+
+		curve.push((wavelength, value));
+	}
+
+	Ok(curve)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::testing::assert_close;
+
+	#[test]
+	fn flat_spectrum_is_grey() {
+		let grey = [ (VISIBLE.0, 0.5), (VISIBLE.1, 0.5) ];
+
+		for illuminant in &[ Illuminant::E, Illuminant::D65, Illuminant::A, Illuminant::BlackBody(10000.0) ] {
+			assert_close(spectrum_to_rgb(&grey, illuminant), [ 0.5, 0.5, 0.5 ], 1e-3);
+		}
+	}
+
+	#[test]
+	fn d65_white() {
+		let white = [ (VISIBLE.0, 1.0) ];
+		let xyz = spectrum_to_xyz(&white, &Illuminant::D65);
+		let sum = xyz[0] + xyz[1] + xyz[2];
+
+		// Chromaticity of D65 is (0.3127, 0.3290), the colour matching functions are a fit
+		assert!((xyz[0] / sum - 0.3127).abs() < 2e-3 && (xyz[1] / sum - 0.3290).abs() < 2e-3, "{:?}", xyz);
+		assert!((xyz[1] - 1.0).abs() < 1e-5);
+		assert_close(xyz_to_rgb(xyz), [ 1.0, 1.0, 1.0 ], 1e-2);
+		assert_close(spectrum_to_rgb(&white, &Illuminant::D65), [ 1.0, 1.0, 1.0 ], 1e-3);
+	}
+
+	#[test]
+	fn curve_format() {
+		assert_eq!(parse_curve("# red\n400 0.1\n\n700 0.9 # peak\n"), Ok(vec![ (400.0, 0.1), (700.0, 0.9) ]));
+		assert_eq!(parse_curve("400 0.1\n400 0.2\n").unwrap_err().0, 2);
+		assert_eq!(parse_curve("400\n").unwrap_err().0, 1);
+		assert!(parse_curve("# nothing\n").is_err());
+	}
+
+	#[test]
+	fn evenly_spaced_format() {
+		assert_eq!(parse_curve("# red\nsp 400 700\n0.1 0.2\n\n0.3 0.4 # peak\n"), Ok(vec![ (400.0, 0.1), (500.0, 0.2), (600.0, 0.3), (700.0, 0.4) ]));
+		assert_eq!(parse_curve("sp 400 700 0.5 0.5\n"), Ok(vec![ (400.0, 0.5), (700.0, 0.5) ]));
+		assert_eq!(parse_curve("sp 400\n0.1 0.2\n").unwrap_err().0, 1);
+		assert_eq!(parse_curve("sp 700 400\n0.1 0.2\n").unwrap_err().0, 1);
+		assert_eq!(parse_curve("sp 400 700\n0.1\n0.2 x\n").unwrap_err().0, 3);
+		assert_eq!(parse_curve("\nsp 400 700\n0.1\n").unwrap_err().0, 2);
+	}
+}
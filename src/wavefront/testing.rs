@@ -0,0 +1,6 @@
+//! Helpers shared by the test modules
+
+/// Check two points or colours are within `tolerance` of each other on every axis
+pub fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+	assert!((0..3).all(|axis| (actual[axis] - expected[axis]).abs() < tolerance), "{:?} != {:?}", actual, expected);
+}
@@ -0,0 +1,508 @@
+/// Split a planar (or nearly planar) polygon into triangles.
+///
+/// The polygon is projected onto its best-fit plane, found with Newell's method, and then
+/// triangulated by ear clipping so concave outlines are handled correctly. Returned triangles
+/// index into `points` and keep the winding order of the input polygon.
+pub fn triangulate(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+	let count = points.len();
+
+	if count < 3 {
+		return Vec::new();
+	}
+
+	if count == 3 {
+		return vec![ [ 0, 1, 2 ] ];
+	}
+
+	let normal = newell_normal(points);
+
+	if length(normal) <= f32::EPSILON {
+		return fan(count);
+	}
+
+	let projected = project(points, normalize(normal));
+
+	ear_clip(&projected)
+}
+
+/// Triangulate a simple polygon in the plane. Triangles are wound counter-clockwise whichever way
+/// the polygon is wound.
+pub fn triangulate_2d(points: &[[f32; 2]]) -> Vec<[usize; 3]> {
+	let count = points.len();
+
+	if count < 3 {
+		return Vec::new();
+	}
+
+	if signed_area(points) >= 0.0 {
+		return ear_clip(points);
+	}
+
+	let reversed: Vec<[f32; 2]> = points.iter().rev().cloned().collect();
+
+	ear_clip(&reversed).into_iter()
+		.map(|triangle| [ count - 1 - triangle[0], count - 1 - triangle[1], count - 1 - triangle[2] ])
+		.collect()
+}
+
+/// Ear clipping of a counter-clockwise polygon
+fn ear_clip(projected: &[[f32; 2]]) -> Vec<[usize; 3]> {
+	let count = projected.len();
+
+	if count < 3 {
+		return Vec::new();
+	}
+
+	// Repeated vertices (as left behind by clipping) make every neighbouring corner degenerate
+	let mut remaining: Vec<usize> = (0..count)
+		.filter(|&i| projected[i] != projected[(i + 1) % count])
+		.collect();
+
+	if remaining.len() < 3 {
+		return Vec::new();
+	}
+
+	let mut triangles = Vec::with_capacity(remaining.len() - 2);
+
+	while remaining.len() > 3 {
+		let len = remaining.len();
+
+		let ear = (0..len).find(|&i| is_ear(projected, &remaining, i));
+
+		// Degenerate or self-intersecting polygons may not have a valid ear. Clip the first
+		// convex vertex (or failing that, any vertex) so the loop always makes progress.
+		let clip = ear
+			.or_else(|| (0..len).find(|&i| is_convex(projected, &remaining, i)))
+			.unwrap_or(0);
+
+		let prev = remaining[(clip + len - 1) % len];
+		let next = remaining[(clip + 1) % len];
+
+		triangles.push([ prev, remaining[clip], next ]);
+		remaining.remove(clip);
+	}
+
+	triangles.push([ remaining[0], remaining[1], remaining[2] ]);
+
+	triangles
+}
+
+/// Normal of a polygon using Newell's method. The result is not normalised; its length is twice
+/// the area of the polygon projected onto its best-fit plane.
+pub fn newell_normal(points: &[[f32; 3]]) -> [f32; 3] {
+	let mut normal = [ 0.0, 0.0, 0.0 ];
+
+	for (i, current) in points.iter().enumerate() {
+		let next = &points[(i + 1) % points.len()];
+
+		normal[0] += (current[1] - next[1]) * (current[2] + next[2]);
+		normal[1] += (current[2] - next[2]) * (current[0] + next[0]);
+		normal[2] += (current[0] - next[0]) * (current[1] + next[1]);
+	}
+
+	normal
+}
+
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+	let len = length(v);
+
+	if len > 0.0 {
+		[ v[0] / len, v[1] / len, v[2] / len ]
+	} else {
+		v
+	}
+}
+
+fn length(v: [f32; 3]) -> f32 {
+	(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+	[ a[0] - b[0], a[1] - b[1], a[2] - b[2] ]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+	[
+		a[1] * b[2] - a[2] * b[1],
+		a[2] * b[0] - a[0] * b[2],
+		a[0] * b[1] - a[1] * b[0],
+	]
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+	a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn fan(count: usize) -> Vec<[usize; 3]> {
+	(1..count - 1).map(|i| [ 0, i, i + 1 ]).collect()
+}
+
+/// Project points onto the plane with the given unit normal. The 2D basis is chosen so the
+/// projected polygon is wound counter-clockwise.
+fn project(points: &[[f32; 3]], normal: [f32; 3]) -> Vec<[f32; 2]> {
+	// Pick the world axis least aligned with the normal to build a stable basis
+	let axis = if normal[0].abs() < 0.577 {
+		[ 1.0, 0.0, 0.0 ]
+	} else if normal[1].abs() < 0.577 {
+		[ 0.0, 1.0, 0.0 ]
+	} else {
+		[ 0.0, 0.0, 1.0 ]
+	};
+
+	let u = normalize(cross(axis, normal));
+	let v = cross(normal, u);
+
+	points.iter().map(|p| [ dot(*p, u), dot(*p, v) ]).collect()
+}
+
+fn area2(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+	(b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn corner(projected: &[[f32; 2]], remaining: &[usize], i: usize) -> ([f32; 2], [f32; 2], [f32; 2]) {
+	let len = remaining.len();
+
+	(
+		projected[remaining[(i + len - 1) % len]],
+		projected[remaining[i]],
+		projected[remaining[(i + 1) % len]],
+	)
+}
+
+fn is_convex(projected: &[[f32; 2]], remaining: &[usize], i: usize) -> bool {
+	let (a, b, c) = corner(projected, remaining, i);
+
+	area2(a, b, c) > 0.0
+}
+
+fn is_ear(projected: &[[f32; 2]], remaining: &[usize], i: usize) -> bool {
+	if !is_convex(projected, remaining, i) {
+		return false;
+	}
+
+	let len = remaining.len();
+	let (a, b, c) = corner(projected, remaining, i);
+
+	// No other vertex of the remaining polygon may lie inside (or on the edge of) the ear
+	(0..len)
+		.filter(|&j| j != i && j != (i + len - 1) % len && j != (i + 1) % len)
+		.map(|j| projected[remaining[j]])
+		.filter(|&p| p != a && p != b && p != c)
+		.all(|p| !(area2(a, b, p) >= 0.0 && area2(b, c, p) >= 0.0 && area2(c, a, p) >= 0.0))
+}
+
+/// Twice the signed area of a polygon, positive if it is wound counter-clockwise
+pub fn signed_area(points: &[[f32; 2]]) -> f32 {
+	(0..points.len())
+		.map(|i| {
+			let (a, b) = (points[i], points[(i + 1) % points.len()]);
+
+			a[0] * b[1] - b[0] * a[1]
+		})
+		.sum()
+}
+
+/// Whether a point is inside a polygon, using the even-odd rule
+pub fn contains(polygon: &[[f32; 2]], point: [f32; 2]) -> bool {
+	let mut inside = false;
+
+	for i in 0..polygon.len() {
+		let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+
+		if (a[1] > point[1]) != (b[1] > point[1]) {
+			let x = a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+
+			if point[0] < x {
+				inside = !inside;
+			}
+		}
+	}
+
+	inside
+}
+
+/// Whether any edge of a polygon passes through the rectangle from `min` to `max`
+pub fn crosses_rect(polygon: &[[f32; 2]], min: [f32; 2], max: [f32; 2]) -> bool {
+	(0..polygon.len()).any(|i| segment_in_rect(polygon[i], polygon[(i + 1) % polygon.len()], min, max))
+}
+
+/// Liang-Barsky test for whether any part of the segment from `a` to `b` lies in the rectangle
+fn segment_in_rect(a: [f32; 2], b: [f32; 2], min: [f32; 2], max: [f32; 2]) -> bool {
+	let (mut enter, mut exit) = (0.0f32, 1.0f32);
+
+	for axis in 0..2 {
+		let delta = b[axis] - a[axis];
+
+		if delta == 0.0 {
+			if a[axis] < min[axis] || a[axis] > max[axis] {
+				return false;
+			}
+
+			continue;
+		}
+
+		let t0 = (min[axis] - a[axis]) / delta;
+		let t1 = (max[axis] - a[axis]) / delta;
+
+		enter = enter.max(t0.min(t1));
+		exit = exit.min(t0.max(t1));
+
+		if enter > exit {
+			return false;
+		}
+	}
+
+	true
+}
+
+/// Clip a convex polygon to a rectangle with Sutherland-Hodgman clipping
+pub fn clip_to_rect(polygon: &[[f32; 2]], min: [f32; 2], max: [f32; 2]) -> Vec<[f32; 2]> {
+	let mut output = polygon.to_vec();
+
+	for &(axis, bound, keep_below) in [ (0, min[0], false), (0, max[0], true), (1, min[1], false), (1, max[1], true) ].iter() {
+		let input = output;
+		let inside = |p: [f32; 2]| if keep_below { p[axis] <= bound } else { p[axis] >= bound };
+
+		output = Vec::with_capacity(input.len() + 4);
+
+		for i in 0..input.len() {
+			let (current, next) = (input[i], input[(i + 1) % input.len()]);
+
+			if inside(current) {
+				output.push(current);
+			}
+
+			if inside(current) != inside(next) {
+				let t = (bound - current[axis]) / (next[axis] - current[axis]);
+				let mut crossing = [
+					current[0] + t * (next[0] - current[0]),
+					current[1] + t * (next[1] - current[1]),
+				];
+
+				crossing[axis] = bound;
+				output.push(crossing);
+			}
+		}
+
+		if output.is_empty() {
+			break;
+		}
+	}
+
+	output
+}
+
+/// Join holes into an outer polygon with bridge edges so the region can be handled as a single
+/// polygon. The outer polygon is wound counter-clockwise and the holes clockwise in the result.
+pub fn merge_holes(outer: &[[f32; 2]], holes: &[Vec<[f32; 2]>]) -> Vec<[f32; 2]> {
+	let mut merged = outer.to_vec();
+
+	if signed_area(&merged) < 0.0 {
+		merged.reverse();
+	}
+
+	let mut holes: Vec<Vec<[f32; 2]>> = holes.iter()
+		.filter(|hole| hole.len() >= 3)
+		.map(|hole| {
+			let mut hole = hole.clone();
+
+			if signed_area(&hole) > 0.0 {
+				hole.reverse();
+			}
+
+			hole
+		})
+		.collect();
+
+	// Bridging the rightmost hole first means later bridges can't cross earlier ones
+	holes.sort_by(|a, b| max_x(b).partial_cmp(&max_x(a)).unwrap_or(::std::cmp::Ordering::Equal));
+
+	for hole in holes.iter() {
+		let start = (0..hole.len())
+			.max_by(|&i, &j| hole[i][0].partial_cmp(&hole[j][0]).unwrap_or(::std::cmp::Ordering::Equal))
+			.unwrap();
+
+		let bridge = match bridge_vertex(&merged, hole[start]) {
+			Some(bridge) => bridge,
+			None => continue,
+		};
+
+		let mut joined = Vec::with_capacity(merged.len() + hole.len() + 2);
+
+		joined.extend_from_slice(&merged[..bridge + 1]);
+		joined.extend((0..hole.len() + 1).map(|i| hole[(start + i) % hole.len()]));
+		joined.extend_from_slice(&merged[bridge..]);
+
+		merged = joined;
+	}
+
+	merged
+}
+
+fn max_x(points: &[[f32; 2]]) -> f32 {
+	points.iter().fold(f32::NEG_INFINITY, |max, p| max.max(p[0]))
+}
+
+/// Find a vertex of a counter-clockwise polygon visible from a point inside it, by casting a ray
+/// in the +x direction and picking the nearest edge it hits
+fn bridge_vertex(polygon: &[[f32; 2]], point: [f32; 2]) -> Option<usize> {
+	let len = polygon.len();
+	let mut nearest: Option<(f32, usize)> = None;
+
+	for i in 0..len {
+		let (a, b) = (polygon[i], polygon[(i + 1) % len]);
+
+		// Only edges going upwards can be hit from the inside of a counter-clockwise polygon
+		if !(a[1] <= point[1] && b[1] >= point[1]) || a[1] == b[1] {
+			continue;
+		}
+
+		let x = a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+
+		if x < point[0] || nearest.is_some_and(|(nearest_x, _)| x >= nearest_x) {
+			continue;
+		}
+
+		let vertex = if a[0] > b[0] { i } else { (i + 1) % len };
+
+		nearest = Some((x, vertex));
+	}
+
+	let (x, candidate) = nearest?;
+	let hit = [ x, point[1] ];
+
+	// A reflex vertex inside the triangle between the point, the hit and the candidate would
+	// block the bridge, so use the one making the smallest angle with the ray instead
+	let mut best = candidate;
+	let mut best_angle = f32::INFINITY;
+
+	for i in 0..len {
+		let p = polygon[i];
+
+		if i == candidate || p == polygon[candidate] {
+			continue;
+		}
+
+		let (prev, next) = (polygon[(i + len - 1) % len], polygon[(i + 1) % len]);
+
+		if area2(prev, p, next) >= 0.0 || !in_triangle(point, hit, polygon[candidate], p) {
+			continue;
+		}
+
+		let angle = (p[1] - point[1]).abs().atan2(p[0] - point[0]);
+
+		if angle < best_angle {
+			best = i;
+			best_angle = angle;
+		}
+	}
+
+	Some(best)
+}
+
+fn in_triangle(a: [f32; 2], b: [f32; 2], c: [f32; 2], p: [f32; 2]) -> bool {
+	let (d1, d2, d3) = (area2(a, b, p), area2(b, c, p), area2(c, a, p));
+
+	!((d1 < 0.0 || d2 < 0.0 || d3 < 0.0) && (d1 > 0.0 || d2 > 0.0 || d3 > 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn flat(points: &[[f32; 2]]) -> Vec<[f32; 3]> {
+		points.iter().map(|p| [ p[0], p[1], 0.0 ]).collect()
+	}
+
+	/// Triangulate a polygon, checking the number of triangles and that their areas, projected
+	/// onto the polygon's plane, are none of them negative and add up to the polygon's area
+	fn check(points: &[[f32; 3]], count: usize) {
+		let triangles = triangulate(points);
+		let normal = newell_normal(points);
+		let unit = normalize(normal);
+
+		assert_eq!(triangles.len(), count, "{:?}", triangles);
+
+		let areas: Vec<f32> = triangles.iter()
+			.map(|t| dot(cross(sub(points[t[1]], points[t[0]]), sub(points[t[2]], points[t[0]])), unit) / 2.0)
+			.collect();
+
+		assert!(areas.iter().all(|&area| area >= -1e-6), "flipped triangle in {:?}: {:?}", triangles, areas);
+
+		let total: f32 = areas.iter().sum();
+		let expected = length(normal) / 2.0;
+
+		assert!((total - expected).abs() <= 1e-5 * expected.max(1.0), "areas {:?} sum to {} not {}", areas, total, expected);
+	}
+
+	#[test]
+	fn convex() {
+		check(&flat(&[ [ 0.0, 0.0 ], [ 1.0, 0.0 ], [ 1.0, 1.0 ] ]), 1);
+		check(&flat(&[ [ 0.0, 0.0 ], [ 1.0, 0.0 ], [ 1.0, 1.0 ], [ 0.0, 1.0 ] ]), 2);
+
+		let hexagon: Vec<[f32; 2]> = (0..6)
+			.map(|i| {
+				let angle = i as f32 * ::std::f32::consts::PI / 3.0;
+
+				[ angle.cos(), angle.sin() ]
+			})
+			.collect();
+
+		check(&flat(&hexagon), 4);
+	}
+
+	#[test]
+	fn concave() {
+		let l_shape = [ [ 0.0, 0.0 ], [ 2.0, 0.0 ], [ 2.0, 1.0 ], [ 1.0, 1.0 ], [ 1.0, 2.0 ], [ 0.0, 2.0 ] ];
+		let arrow = [ [ 0.0, 0.0 ], [ 2.0, 1.0 ], [ 0.0, 2.0 ], [ 1.0, 1.0 ] ];
+
+		check(&flat(&l_shape), 4);
+		check(&flat(&arrow), 2);
+
+		// A fan from the first vertex of the arrow would overlap itself
+		assert!(triangulate(&flat(&arrow)).iter().all(|t| !(t.contains(&0) && t.contains(&2) && t.contains(&3))));
+
+		// Clockwise, and standing in the xz plane
+		let upright: Vec<[f32; 3]> = l_shape.iter().rev().map(|p| [ p[0], 5.0, p[1] ]).collect();
+
+		check(&upright, 4);
+	}
+
+	#[test]
+	fn collinear_vertices() {
+		let square = [
+			[ 0.0, 0.0 ], [ 1.0, 0.0 ], [ 2.0, 0.0 ], [ 2.0, 1.0 ],
+			[ 2.0, 2.0 ], [ 1.0, 2.0 ], [ 0.0, 2.0 ], [ 0.0, 1.0 ],
+		];
+
+		check(&flat(&square), 6);
+	}
+
+	#[test]
+	fn non_planar() {
+		check(&[ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ], [ 1.0, 1.0, 0.1 ], [ 0.0, 1.0, 0.0 ] ], 2);
+		check(&[ [ 0.0, 0.0, 0.0 ], [ 2.0, 0.0, 0.2 ], [ 2.0, 1.0, 0.0 ], [ 1.0, 1.0, 0.3 ], [ 1.0, 2.0, 0.0 ], [ 0.0, 2.0, -0.2 ] ], 4);
+	}
+
+	#[test]
+	fn degenerate() {
+		assert!(triangulate(&[]).is_empty());
+		assert!(triangulate(&[ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ] ]).is_empty());
+
+		// No area, so no plane to project onto
+		check(&[ [ 0.0, 0.0, 0.0 ], [ 1.0, 0.0, 0.0 ], [ 2.0, 0.0, 0.0 ], [ 3.0, 0.0, 0.0 ] ], 2);
+
+		// A repeated vertex is skipped rather than producing a sliver
+		check(&flat(&[ [ 0.0, 0.0 ], [ 1.0, 0.0 ], [ 1.0, 0.0 ], [ 1.0, 1.0 ], [ 0.0, 1.0 ] ]), 2);
+	}
+
+	#[test]
+	fn clockwise_2d() {
+		let clockwise = [ [ 0.0, 2.0 ], [ 1.0, 2.0 ], [ 1.0, 1.0 ], [ 2.0, 1.0 ], [ 2.0, 0.0 ], [ 0.0, 0.0 ] ];
+		let triangles = triangulate_2d(&clockwise);
+
+		assert_eq!(triangles.len(), 4);
+		assert!(triangles.iter().all(|t| area2(clockwise[t[0]], clockwise[t[1]], clockwise[t[2]]) > 0.0));
+		assert_eq!(triangles.iter().map(|t| area2(clockwise[t[0]], clockwise[t[1]], clockwise[t[2]])).sum::<f32>(), 6.0);
+	}
+}